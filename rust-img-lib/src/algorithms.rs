@@ -1,4 +1,7 @@
+pub mod blockcompression;
 pub mod iconpacker;
+pub mod pixelscalers;
+pub mod spritedetection;
 pub mod spritesheetpackers;
 
 use std::hash::Hash;
@@ -0,0 +1,848 @@
+//! CPU encoders for the BC1, BC3 and BC7 GPU texture formats.
+//!
+//! These aim for decent quality at a reasonable speed rather than the best possible result:
+//! the endpoints of each block come from the principal axis of its colors, refined once with least squares.
+//! BC7 only uses modes 5 and 6, which cover the whole block with a single subset
+
+use image::RgbaImage;
+use wasm_bindgen::prelude::*;
+
+/// The block compression formats that can be written
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockCompression
+{
+    /// 4 bits per pixel, with 1-bit alpha
+    Bc1,
+    /// 8 bits per pixel, with smooth alpha (aka DXT5)
+    Bc3,
+    /// 8 bits per pixel, highest quality
+    Bc7
+}
+
+impl BlockCompression
+{
+    /// Bytes per 4x4 block
+    pub fn block_size(self) -> usize
+    {
+        match self {
+            BlockCompression::Bc1 => 8,
+            BlockCompression::Bc3 | BlockCompression::Bc7 => 16
+        }
+    }
+}
+
+type Block = [[u8; 4]; 16];
+
+/// Compresses the whole image, block by block from the top left. Blocks that hang over the edge repeat the last row/column
+pub fn compress_image(img: &RgbaImage, format: BlockCompression) -> Vec<u8>
+{
+    let (blocks_x, blocks_y) = (img.width().div_ceil(4), img.height().div_ceil(4));
+    let mut out = Vec::with_capacity((blocks_x * blocks_y) as usize * format.block_size());
+    for block_y in 0..blocks_y
+    {
+        for block_x in 0..blocks_x
+        {
+            let mut block: Block = [[0; 4]; 16];
+            for (i, px) in block.iter_mut().enumerate()
+            {
+                let x = (block_x * 4 + i as u32 % 4).min(img.width() - 1);
+                let y = (block_y * 4 + i as u32 / 4).min(img.height() - 1);
+                *px = img.get_pixel(x, y).0;
+            }
+
+            match format {
+                BlockCompression::Bc1 => out.extend_from_slice(&encode_bc1(&block)),
+                BlockCompression::Bc3 => {
+                    out.extend_from_slice(&encode_alpha_block(&block));
+                    out.extend_from_slice(&encode_color_block(&block, false));
+                },
+                BlockCompression::Bc7 => out.extend_from_slice(&encode_bc7(&block))
+            }
+        }
+    }
+    out
+}
+
+fn encode_bc1(block: &Block) -> [u8; 8]
+{
+    let has_transparency = block.iter().any(|px| px[3] < 128);
+    encode_color_block(block, has_transparency)
+}
+
+#[inline]
+fn to_float(px: [u8; 4]) -> [f32; 4]
+{
+    px.map(|c| c as f32)
+}
+
+#[inline]
+fn squared_error(a: [f32; 4], b: [f32; 4]) -> f32
+{
+    a.iter().zip(&b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+#[inline]
+fn lerp(e0: [f32; 4], e1: [f32; 4], t: f32) -> [f32; 4]
+{
+    let mut out = [0.0; 4];
+    for ((o, a), b) in out.iter_mut().zip(&e0).zip(&e1)
+    {
+        *o = a + (b - a) * t;
+    }
+    out
+}
+
+/// Fits a line through the colors, and returns the two ends of the part of it that the colors cover
+fn principal_endpoints(colors: &[[f32; 4]]) -> ([f32; 4], [f32; 4])
+{
+    let count = colors.len().max(1) as f32;
+    let mut mean = [0.0f32; 4];
+    for c in colors
+    {
+        for (m, v) in mean.iter_mut().zip(c)
+        {
+            *m += v / count;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for c in colors
+    {
+        let d: Vec<f32> = c.iter().zip(&mean).map(|(v, m)| v - m).collect();
+        for (row, di) in covariance.iter_mut().zip(&d)
+        {
+            for (cell, dj) in row.iter_mut().zip(&d)
+            {
+                *cell += di * dj;
+            }
+        }
+    }
+
+    // power iteration, starting from the diagonal so that single-channel ramps converge straight away
+    let mut axis = [1.0f32; 4];
+    for _ in 0..8
+    {
+        let mut next = [0.0f32; 4];
+        for (n, row) in next.iter_mut().zip(&covariance)
+        {
+            *n = row.iter().zip(&axis).map(|(c, a)| c * a).sum();
+        }
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < 1e-6
+        {
+            break;
+        }
+        axis = next.map(|v| v / length);
+    }
+
+    let project = |c: &[f32; 4]| c.iter().zip(&mean).zip(&axis).map(|((v, m), a)| (v - m) * a).sum::<f32>();
+    let (min_t, max_t) = colors
+        .iter()
+        .map(project)
+        .fold((0.0f32, 0.0f32), |(lo, hi), t| (lo.min(t), hi.max(t)));
+
+    let end = |t: f32| {
+        let mut e = [0.0f32; 4];
+        for ((e, m), a) in e.iter_mut().zip(&mean).zip(&axis)
+        {
+            *e = (m + a * t).clamp(0.0, 255.0);
+        }
+        e
+    };
+    (end(min_t), end(max_t))
+}
+
+/// Least squares fit of the endpoints, given where along the line (0 to 1) each color was placed.
+/// Returns `None` if every color was placed at the same spot
+fn refine_endpoints(colors: &[[f32; 4]], weights: &[f32]) -> Option<([f32; 4], [f32; 4])>
+{
+    let (mut a, mut b, mut d) = (0.0f32, 0.0f32, 0.0f32);
+    let (mut x0, mut x1) = ([0.0f32; 4], [0.0f32; 4]);
+    for (c, &w) in colors.iter().zip(weights)
+    {
+        a += (1.0 - w) * (1.0 - w);
+        b += (1.0 - w) * w;
+        d += w * w;
+        for ((x0, x1), v) in x0.iter_mut().zip(x1.iter_mut()).zip(c)
+        {
+            *x0 += (1.0 - w) * v;
+            *x1 += w * v;
+        }
+    }
+
+    let det = a * d - b * b;
+    if det.abs() < 1e-6
+    {
+        return None;
+    }
+    let (mut e0, mut e1) = ([0.0f32; 4], [0.0f32; 4]);
+    for i in 0..4
+    {
+        e0[i] = ((d * x0[i] - b * x1[i]) / det).clamp(0.0, 255.0);
+        e1[i] = ((a * x1[i] - b * x0[i]) / det).clamp(0.0, 255.0);
+    }
+    Some((e0, e1))
+}
+
+#[inline]
+fn to_565(c: [f32; 4]) -> u16
+{
+    let r = (c[0] * 31.0 / 255.0).round() as u16;
+    let g = (c[1] * 63.0 / 255.0).round() as u16;
+    let b = (c[2] * 31.0 / 255.0).round() as u16;
+    (r << 11) | (g << 5) | b
+}
+
+#[inline]
+fn from_565(c: u16) -> [f32; 4]
+{
+    let (r, g, b) = ((c >> 11) & 31, (c >> 5) & 63, c & 31);
+    [((r << 3) | (r >> 2)) as f32, ((g << 2) | (g >> 4)) as f32, ((b << 3) | (b >> 2)) as f32, 255.0]
+}
+
+/// The BC1 color block. In three color mode, pixels with alpha under 128 use the transparent color
+fn encode_color_block(block: &Block, three_color_mode: bool) -> [u8; 8]
+{
+    // the color of transparent pixels doesn't matter
+    let colors: Vec<[f32; 4]> = block
+        .iter()
+        .filter(|px| if three_color_mode { px[3] >= 128 } else { px[3] > 0 })
+        .map(|&px| { let mut c = to_float(px); c[3] = 0.0; c })
+        .collect();
+    if colors.is_empty()
+    {
+        return [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+    }
+
+    let (e0, e1) = principal_endpoints(&colors);
+    let (mut best, best_error, weights) = try_color_endpoints(block, to_565(e0), to_565(e1), three_color_mode);
+    if let Some((r0, r1)) = refine_endpoints(&colors, &weights)
+    {
+        let (refined, refined_error, _) = try_color_endpoints(block, to_565(r0), to_565(r1), three_color_mode);
+        if refined_error < best_error
+        {
+            best = refined;
+        }
+    }
+    best
+}
+
+/// Encodes the block with the given endpoints, and returns the block along with its error and the position of each counted color along the line
+fn try_color_endpoints(block: &Block, c0: u16, c1: u16, three_color_mode: bool) -> ([u8; 8], f32, Vec<f32>)
+{
+    // the order of the endpoints is what tells the decoder which mode the block uses
+    let (c0, c1) = if three_color_mode == (c0 > c1) { (c1, c0) } else { (c0, c1) };
+    let (p0, p1) = (from_565(c0), from_565(c1));
+    let palette: Vec<([f32; 4], f32)> = if three_color_mode || c0 == c1 {
+        vec![(p0, 0.0), (p1, 1.0), (lerp(p0, p1, 0.5), 0.5)]
+    } else {
+        vec![(p0, 0.0), (p1, 1.0), (lerp(p0, p1, 1.0 / 3.0), 1.0 / 3.0), (lerp(p0, p1, 2.0 / 3.0), 2.0 / 3.0)]
+    };
+
+    let mut indices = 0u32;
+    let mut error = 0.0;
+    let mut weights = vec![];
+    for (i, px) in block.iter().enumerate()
+    {
+        let index = if three_color_mode && px[3] < 128 {
+            3
+        } else {
+            let mut c = to_float(*px);
+            c[3] = 255.0;
+            let (index, (entry, weight)) = palette
+                .iter()
+                .enumerate()
+                .min_by(|(_, (a, _)), (_, (b, _))| squared_error(*a, c).total_cmp(&squared_error(*b, c)))
+                .expect("Palette can't be empty");
+            if px[3] > 0
+            {
+                error += squared_error(*entry, c);
+                weights.push(*weight);
+            }
+            index as u32
+        };
+        indices |= index << (i * 2);
+    }
+
+    let mut out = [0u8; 8];
+    out[0..2].copy_from_slice(&c0.to_le_bytes());
+    out[2..4].copy_from_slice(&c1.to_le_bytes());
+    out[4..8].copy_from_slice(&indices.to_le_bytes());
+    (out, error, weights)
+}
+
+/// The BC3 alpha block. Tries both the 8 value ramp and the 6 value ramp with explicit 0 and 255, and keeps the better one
+fn encode_alpha_block(block: &Block) -> [u8; 8]
+{
+    let alphas: Vec<u8> = block.iter().map(|px| px[3]).collect();
+    let max = *alphas.iter().max().unwrap_or(&0);
+    let min = *alphas.iter().min().unwrap_or(&0);
+
+    // a0 > a1 means 8 interpolated values
+    let eight_value = {
+        let mut palette = vec![max, min];
+        for k in 1..7u32
+        {
+            palette.push((((7 - k) * max as u32 + k * min as u32 + 3) / 7) as u8);
+        }
+        palette
+    };
+    let mut candidates = vec![((max, min), eight_value)];
+
+    // a0 <= a1 means 6 interpolated values, plus 0 and 255
+    let inner: Vec<u8> = alphas.iter().copied().filter(|&a| a != 0 && a != 255).collect();
+    if let (Some(&inner_min), Some(&inner_max)) = (inner.iter().min(), inner.iter().max())
+    {
+        let mut palette = vec![inner_min, inner_max];
+        for k in 1..5u32
+        {
+            palette.push((((5 - k) * inner_min as u32 + k * inner_max as u32 + 2) / 5) as u8);
+        }
+        palette.extend([0, 255]);
+        candidates.push(((inner_min, inner_max), palette));
+    }
+
+    let mut best = ([0u8; 8], u32::MAX);
+    for ((a0, a1), palette) in candidates
+    {
+        let mut indices = 0u64;
+        let mut error = 0;
+        for (i, &alpha) in alphas.iter().enumerate()
+        {
+            let (index, diff) = palette
+                .iter()
+                .map(|&p| p.abs_diff(alpha) as u32)
+                .enumerate()
+                .min_by_key(|(_, diff)| *diff)
+                .expect("Palette can't be empty");
+            error += diff * diff;
+            indices |= (index as u64) << (i * 3);
+        }
+
+        if error < best.1
+        {
+            let mut out = [0u8; 8];
+            out[0] = a0;
+            out[1] = a1;
+            out[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+            best = (out, error);
+        }
+    }
+    best.0
+}
+
+/// Interpolation weights (out of 64) for 4-bit BC7 indices
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Interpolation weights (out of 64) for 2-bit BC7 indices
+const BC7_WEIGHTS_2BIT: [u32; 4] = [0, 21, 43, 64];
+
+#[inline]
+fn bc7_interpolate(e0: u32, e1: u32, weight: u32) -> u32
+{
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+/// A BC7 mode 6 endpoint: 7 bits per channel, plus a shared low bit
+#[derive(Clone, Copy)]
+struct Mode6Endpoint
+{
+    channels: [u8; 4],
+    p_bit: u8
+}
+
+impl Mode6Endpoint
+{
+    /// Picks the p-bit that gets closest to the color. Fully transparent and fully opaque alpha always stay exact
+    fn quantize(color: [f32; 4]) -> Self
+    {
+        let with_p_bit = |p_bit: u8| {
+            let channels = color.map(|c| ((c - p_bit as f32) / 2.0).round().clamp(0.0, 127.0) as u8);
+            let endpoint = Self { channels, p_bit };
+            (endpoint, squared_error(endpoint.expand(), color))
+        };
+        let (even, even_error) = with_p_bit(0);
+        let (odd, odd_error) = with_p_bit(1);
+        if color[3] < 0.5
+        {
+            even
+        }
+        else if color[3] > 254.5 || odd_error < even_error
+        {
+            odd
+        }
+        else
+        {
+            even
+        }
+    }
+
+    fn expand(&self) -> [f32; 4]
+    {
+        self.channels.map(|c| ((c << 1) | self.p_bit) as f32)
+    }
+}
+
+/// Tries mode 6 (RGBA on one line) and mode 5 (color and alpha fitted separately), and keeps whichever is closer
+fn encode_bc7(block: &Block) -> [u8; 16]
+{
+    // the color of fully transparent pixels doesn't matter, so they take the average color to keep them out of the fit
+    let visible: Vec<[f32; 4]> = block.iter().filter(|px| px[3] > 0).map(|&px| to_float(px)).collect();
+    let mut average = [0.0f32; 4];
+    for c in &visible
+    {
+        for (a, v) in average.iter_mut().zip(c)
+        {
+            *a += v / visible.len() as f32;
+        }
+    }
+    let colors: Vec<[f32; 4]> = block
+        .iter()
+        .map(|&px| if px[3] == 0 { [average[0], average[1], average[2], 0.0] } else { to_float(px) })
+        .collect();
+
+    let (mode6, mode6_error) = encode_bc7_mode6(&colors);
+    let (mode5, mode5_error) = encode_bc7_mode5(&colors);
+    if mode5_error < mode6_error { mode5 } else { mode6 }
+}
+
+fn encode_bc7_mode6(colors: &[[f32; 4]]) -> ([u8; 16], f32)
+{
+    let (e0, e1) = principal_endpoints(colors);
+    let (mut best, mut best_error, weights) = try_mode6_endpoints(colors, Mode6Endpoint::quantize(e0), Mode6Endpoint::quantize(e1));
+    if let Some((r0, r1)) = refine_endpoints(colors, &weights)
+    {
+        let (refined, refined_error, _) = try_mode6_endpoints(colors, Mode6Endpoint::quantize(r0), Mode6Endpoint::quantize(r1));
+        if refined_error < best_error
+        {
+            (best, best_error) = (refined, refined_error);
+        }
+    }
+    (best, best_error)
+}
+
+fn try_mode6_endpoints(colors: &[[f32; 4]], e0: Mode6Endpoint, e1: Mode6Endpoint) -> ([u8; 16], f32, Vec<f32>)
+{
+    let (p0, p1) = (e0.expand(), e1.expand());
+    let palette: Vec<[f32; 4]> = BC7_WEIGHTS
+        .iter()
+        .map(|&w| {
+            let mut c = [0.0f32; 4];
+            for ((c, a), b) in c.iter_mut().zip(&p0).zip(&p1)
+            {
+                *c = bc7_interpolate(*a as u32, *b as u32, w) as f32;
+            }
+            c
+        })
+        .collect();
+
+    let mut indices = [0u8; 16];
+    let mut error = 0.0;
+    for (index, c) in indices.iter_mut().zip(colors)
+    {
+        // fully transparent pixels have to stay that way, or they would show up as faint fringes around sprites
+        let pixel_error = |entry: &[f32; 4]| if c[3] == 0.0 { if entry[3] == 0.0 { 0.0 } else { 1e9 } } else { squared_error(*entry, *c) };
+        let (i, entry) = palette
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| pixel_error(a).total_cmp(&pixel_error(b)))
+            .expect("Palette can't be empty");
+        error += pixel_error(entry);
+        *index = i as u8;
+    }
+    let weights = indices.iter().map(|&i| BC7_WEIGHTS[i as usize] as f32 / 64.0).collect();
+
+    // the first index is stored without its top bit, so it has to be in the lower half
+    let (e0, e1) = if indices[0] >= 8
+    {
+        for index in &mut indices
+        {
+            *index = 15 - *index;
+        }
+        (e1, e0)
+    }
+    else
+    {
+        (e0, e1)
+    };
+
+    let mut bits = BitWriter::default();
+    bits.push(1 << 6, 7);
+    for channel in 0..4
+    {
+        bits.push(e0.channels[channel] as u128, 7);
+        bits.push(e1.channels[channel] as u128, 7);
+    }
+    bits.push(e0.p_bit as u128, 1);
+    bits.push(e1.p_bit as u128, 1);
+    for (i, &index) in indices.iter().enumerate()
+    {
+        bits.push(index as u128, if i == 0 { 3 } else { 4 });
+    }
+    (bits.value.to_le_bytes(), error, weights)
+}
+
+/// Mode 5 (without channel rotation): 7-bit RGB endpoints and 8-bit alpha endpoints, each with their own 2-bit indices
+fn encode_bc7_mode5(colors: &[[f32; 4]]) -> ([u8; 16], f32)
+{
+    let quantize = |c: [f32; 4]| [0, 1, 2].map(|i| (c[i] * 127.0 / 255.0).round() as u32);
+    let rgb: Vec<[f32; 4]> = colors.iter().map(|c| [c[0], c[1], c[2], 0.0]).collect();
+    let (e0, e1) = principal_endpoints(&rgb);
+    let (mut color_part, mut color_error, weights) = try_mode5_color(colors, quantize(e0), quantize(e1));
+    if let Some((r0, r1)) = refine_endpoints(&rgb, &weights)
+    {
+        let refined = try_mode5_color(colors, quantize(r0), quantize(r1));
+        if refined.1 < color_error
+        {
+            (color_part, color_error) = (refined.0, refined.1);
+        }
+    }
+    let (mut color_endpoints, mut color_indices) = color_part;
+
+    let alpha_min = colors.iter().map(|c| c[3] as u32).min().unwrap_or(0);
+    let alpha_max = colors.iter().map(|c| c[3] as u32).max().unwrap_or(0);
+    let mut alpha_endpoints = [alpha_min, alpha_max];
+    let mut alpha_indices = [0u8; 16];
+    let mut alpha_error = 0.0;
+    for (index, c) in alpha_indices.iter_mut().zip(colors)
+    {
+        let (i, diff) = BC7_WEIGHTS_2BIT
+            .iter()
+            .map(|&w| (bc7_interpolate(alpha_min, alpha_max, w) as f32 - c[3]).abs())
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("Palette can't be empty");
+        alpha_error += diff * diff;
+        *index = i as u8;
+    }
+
+    // the first index of each set is stored without its top bit, so it has to be in the lower half
+    if color_indices[0] >= 2
+    {
+        color_endpoints.swap(0, 1);
+        color_indices = color_indices.map(|i| 3 - i);
+    }
+    if alpha_indices[0] >= 2
+    {
+        alpha_endpoints.swap(0, 1);
+        alpha_indices = alpha_indices.map(|i| 3 - i);
+    }
+
+    let mut bits = BitWriter::default();
+    bits.push(1 << 5, 6);
+    // no channel rotation
+    bits.push(0, 2);
+    for (&c0, &c1) in color_endpoints[0].iter().zip(&color_endpoints[1])
+    {
+        bits.push(c0 as u128, 7);
+        bits.push(c1 as u128, 7);
+    }
+    bits.push(alpha_endpoints[0] as u128, 8);
+    bits.push(alpha_endpoints[1] as u128, 8);
+    for indices in [color_indices, alpha_indices]
+    {
+        for (i, &index) in indices.iter().enumerate()
+        {
+            bits.push(index as u128, if i == 0 { 1 } else { 2 });
+        }
+    }
+    (bits.value.to_le_bytes(), color_error + alpha_error)
+}
+
+/// 7-bit RGB endpoints, and the color index of each pixel
+type Mode5Color = ([[u32; 3]; 2], [u8; 16]);
+
+/// Picks the color indices for mode 5, and returns them along with the error and the weight of each pixel
+fn try_mode5_color(colors: &[[f32; 4]], e0: [u32; 3], e1: [u32; 3]) -> (Mode5Color, f32, Vec<f32>)
+{
+    let expand = |e: [u32; 3]| e.map(|c| (c << 1) | (c >> 6));
+    let (p0, p1) = (expand(e0), expand(e1));
+    let palette: Vec<[f32; 4]> = BC7_WEIGHTS_2BIT
+        .iter()
+        .map(|&w| [0, 1, 2, 3].map(|i| if i < 3 { bc7_interpolate(p0[i], p1[i], w) as f32 } else { 0.0 }))
+        .collect();
+
+    let mut indices = [0u8; 16];
+    let mut error = 0.0;
+    let mut weights = vec![];
+    for (index, c) in indices.iter_mut().zip(colors)
+    {
+        let rgb = [c[0], c[1], c[2], 0.0];
+        let (i, entry) = palette
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| squared_error(**a, rgb).total_cmp(&squared_error(**b, rgb)))
+            .expect("Palette can't be empty");
+        if c[3] > 0.0
+        {
+            error += squared_error(*entry, rgb);
+        }
+        weights.push(BC7_WEIGHTS_2BIT[i] as f32 / 64.0);
+        *index = i as u8;
+    }
+    (([e0, e1], indices), error, weights)
+}
+
+/// Packs values into a 128-bit block, least significant bit first
+#[derive(Default)]
+struct BitWriter
+{
+    value: u128,
+    position: u32
+}
+
+impl BitWriter
+{
+    fn push(&mut self, value: u128, bit_count: u32)
+    {
+        self.value |= value << self.position;
+        self.position += bit_count;
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests
+{
+    use std::{convert::TryInto, io::Cursor};
+
+    use image::codecs::dxt::{DxtDecoder, DxtVariant};
+    use image::{DynamicImage, Rgba};
+
+    use super::*;
+
+    fn decode(data: &[u8], width: u32, height: u32, format: BlockCompression) -> RgbaImage
+    {
+        let variant = match format {
+            BlockCompression::Bc1 => DxtVariant::DXT1,
+            BlockCompression::Bc3 => DxtVariant::DXT5,
+            BlockCompression::Bc7 => unreachable!("image can't decode BC7")
+        };
+        let decoder = DxtDecoder::new(Cursor::new(data), width, height, variant).expect("Could not create the decoder");
+        DynamicImage::from_decoder(decoder).expect("Could not decode the blocks").to_rgba8()
+    }
+
+    /// Reads a block's fields in order, least significant bit first
+    struct BitReader(u128);
+
+    impl BitReader
+    {
+        fn read(&mut self, bit_count: u32) -> u32
+        {
+            let value = (self.0 & ((1 << bit_count) - 1)) as u32;
+            self.0 >>= bit_count;
+            value
+        }
+    }
+
+    /// Decodes a BC7 block, following the format's spec for the only modes the encoder writes (5 and 6)
+    fn decode_bc7_block(block: &[u8]) -> [[u8; 4]; 16]
+    {
+        let mut bits = BitReader(u128::from_le_bytes(block.try_into().expect("Blocks are 16 bytes")));
+        let mut pixels = [[0u8; 4]; 16];
+        match bits.0.trailing_zeros() {
+            6 => {
+                bits.read(7);
+                let mut endpoints = [[0u32; 4]; 2];
+                for channel in 0..4
+                {
+                    endpoints[0][channel] = bits.read(7);
+                    endpoints[1][channel] = bits.read(7);
+                }
+                for endpoint in &mut endpoints
+                {
+                    let p_bit = bits.read(1);
+                    *endpoint = endpoint.map(|c| (c << 1) | p_bit);
+                }
+                for (i, px) in pixels.iter_mut().enumerate()
+                {
+                    let weight = BC7_WEIGHTS[bits.read(if i == 0 { 3 } else { 4 }) as usize];
+                    *px = [0, 1, 2, 3].map(|c| bc7_interpolate(endpoints[0][c], endpoints[1][c], weight) as u8);
+                }
+            },
+            5 => {
+                bits.read(6);
+                assert_eq!(bits.read(2), 0, "The encoder never rotates channels");
+                let mut endpoints = [[0u32; 4]; 2];
+                for channel in 0..3
+                {
+                    endpoints[0][channel] = bits.read(7);
+                    endpoints[1][channel] = bits.read(7);
+                }
+                endpoints[0][3] = bits.read(8);
+                endpoints[1][3] = bits.read(8);
+                for endpoint in &mut endpoints
+                {
+                    for c in &mut endpoint[..3]
+                    {
+                        *c = (*c << 1) | (*c >> 6);
+                    }
+                }
+                for channels in [0..3, 3..4]
+                {
+                    for (i, px) in pixels.iter_mut().enumerate()
+                    {
+                        let weight = BC7_WEIGHTS_2BIT[bits.read(if i == 0 { 1 } else { 2 }) as usize];
+                        for c in channels.clone()
+                        {
+                            px[c] = bc7_interpolate(endpoints[0][c], endpoints[1][c], weight) as u8;
+                        }
+                    }
+                }
+            },
+            mode => panic!("The encoder only writes modes 5 and 6, got mode {}", mode)
+        }
+        pixels
+    }
+
+    fn decode_bc7(data: &[u8], width: u32, height: u32) -> RgbaImage
+    {
+        let blocks_wide = width.div_ceil(4);
+        let mut img = RgbaImage::new(width, height);
+        for (i, block) in data.chunks(16).enumerate()
+        {
+            let (block_x, block_y) = (i as u32 % blocks_wide * 4, i as u32 / blocks_wide * 4);
+            for (j, &px) in decode_bc7_block(block).iter().enumerate()
+            {
+                let (x, y) = (block_x + j as u32 % 4, block_y + j as u32 / 4);
+                if x < width && y < height
+                {
+                    img.put_pixel(x, y, Rgba(px));
+                }
+            }
+        }
+        img
+    }
+
+    fn max_color_error(a: &RgbaImage, b: &RgbaImage) -> u8
+    {
+        a.pixels().zip(b.pixels()).flat_map(|(a, b)| (0..3).map(move |c| a[c].abs_diff(b[c]))).max().unwrap_or(0)
+    }
+
+    /// Colors that lie on a line within every block, which is all BC1 and BC3 can store
+    fn gradient(alpha: impl Fn(u32, u32) -> u8) -> RgbaImage
+    {
+        RgbaImage::from_fn(16, 16, |x, y| {
+            let t = (y * 16 + x) as u8;
+            Rgba([t, 255 - t, 64 + t / 2, alpha(x, y)])
+        })
+    }
+
+    #[test]
+    fn output_size_covers_partial_blocks()
+    {
+        let img = RgbaImage::new(5, 9);
+        assert_eq!(compress_image(&img, BlockCompression::Bc1).len(), 2 * 3 * 8);
+        assert_eq!(compress_image(&img, BlockCompression::Bc3).len(), 2 * 3 * 16);
+        assert_eq!(compress_image(&img, BlockCompression::Bc7).len(), 2 * 3 * 16);
+    }
+
+    #[test]
+    fn representable_colors_round_trip_exactly()
+    {
+        // every channel is a value that 565 can store, so flat blocks decode to the same color
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255], [0, 0, 0], [132, 130, 132]];
+        let img = RgbaImage::from_fn(4 * colors.len() as u32, 4, |x, _| {
+            let [r, g, b] = colors[x as usize / 4];
+            Rgba([r, g, b, 255])
+        });
+        for &format in &[BlockCompression::Bc1, BlockCompression::Bc3]
+        {
+            // decoders are allowed to round the 565 expansion differently
+            let decoded = decode(&compress_image(&img, format), img.width(), 4, format);
+            assert!(max_color_error(&img, &decoded) <= 1, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn bc1_gradient_stays_close()
+    {
+        let img = gradient(|_, _| 255);
+        let decoded = decode(&compress_image(&img, BlockCompression::Bc1), 16, 16, BlockCompression::Bc1);
+        let error = max_color_error(&img, &decoded);
+        assert!(error <= 8, "max error was {}", error);
+    }
+
+    #[test]
+    fn bc1_transparent_pixels_use_the_transparent_index()
+    {
+        let img = gradient(|x, y| if (x + y) % 3 == 0 { 0 } else { 255 });
+        let data = compress_image(&img, BlockCompression::Bc1);
+        for block in data.chunks(8)
+        {
+            // three color mode is what makes index 3 transparent
+            assert!(u16::from_le_bytes([block[0], block[1]]) <= u16::from_le_bytes([block[2], block[3]]));
+        }
+
+        // image's decoder drops alpha, and shows the transparent index as black
+        let decoded = decode(&data, 16, 16, BlockCompression::Bc1);
+        for (original, decoded) in img.pixels().zip(decoded.pixels())
+        {
+            if original[3] == 0
+            {
+                assert_eq!(decoded.0, [0, 0, 0, 255]);
+            }
+            else
+            {
+                assert!((0..3).all(|c| original[c].abs_diff(decoded[c]) <= 16), "{:?} became {:?}", original, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn bc3_keeps_smooth_alpha()
+    {
+        let img = gradient(|x, y| if x == 0 { 0 } else if x == 15 { 255 } else { (x * 8 + y * 6) as u8 });
+        let decoded = decode(&compress_image(&img, BlockCompression::Bc3), 16, 16, BlockCompression::Bc3);
+        let error = max_color_error(&img, &decoded);
+        assert!(error <= 8, "max color error was {}", error);
+        for (original, decoded) in img.pixels().zip(decoded.pixels())
+        {
+            if original[3] == 0 || original[3] == 255
+            {
+                assert_eq!(original[3], decoded[3]);
+            }
+            else
+            {
+                assert!(original[3].abs_diff(decoded[3]) <= 4, "alpha {} became {}", original[3], decoded[3]);
+            }
+        }
+    }
+
+    #[test]
+    fn bc7_flat_blocks_round_trip_exactly()
+    {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255], [0, 0, 0, 255], [12, 34, 56, 128]];
+        let img = RgbaImage::from_fn(4 * colors.len() as u32, 4, |x, _| Rgba(colors[x as usize / 4]));
+        let decoded = decode_bc7(&compress_image(&img, BlockCompression::Bc7), img.width(), 4);
+        assert_eq!(decoded, img);
+    }
+
+    #[test]
+    fn bc7_gradient_stays_close()
+    {
+        let img = gradient(|x, y| 255 - (x * 4 + y * 3) as u8);
+        let decoded = decode_bc7(&compress_image(&img, BlockCompression::Bc7), 16, 16);
+        let error = max_color_error(&img, &decoded);
+        assert!(error <= 4, "max color error was {}", error);
+        for (original, decoded) in img.pixels().zip(decoded.pixels())
+        {
+            assert!(original[3].abs_diff(decoded[3]) <= 4, "alpha {} became {}", original[3], decoded[3]);
+        }
+    }
+
+    #[test]
+    fn bc7_keeps_transparent_and_opaque_pixels_exact()
+    {
+        let img = gradient(|x, y| match (x + y) % 3 { 0 => 0, 1 => 255, _ => 100 });
+        let decoded = decode_bc7(&compress_image(&img, BlockCompression::Bc7), 16, 16);
+        for (original, decoded) in img.pixels().zip(decoded.pixels())
+        {
+            if original[3] == 0 || original[3] == 255
+            {
+                assert_eq!(original[3], decoded[3]);
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Upscalers made for pixel art.
+//!
+//! Each pass only scales by a small whole number factor, so `upscale` chains passes, and refuses any size they can't reach exactly
+//! (resizing the result with another filter would undo the edges these exist to keep)
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// The pixel art upscaling algorithms available
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelScaler
+{
+    /// Scale2x/Scale3x (aka EPX/AdvMAME)
+    Epx,
+    /// An approximation of hq2x: uses the same YUV similarity thresholds as hqx, but with a reduced set of blending rules instead of the full pattern table
+    HqxLite,
+    /// xBR (level 1) 2x, blending halfway along detected edges
+    Xbr
+}
+
+impl PixelScaler
+{
+    /// The factors a single pass can scale by, biggest first
+    fn pass_factors(&self) -> &'static [u32]
+    {
+        match self {
+            PixelScaler::Epx => &[3, 2],
+            PixelScaler::HqxLite | PixelScaler::Xbr => &[2]
+        }
+    }
+
+    /// The passes that scale by exactly `factor`, or `None` if no chain of passes does
+    fn passes_for(&self, factor: u32) -> Option<Vec<u32>>
+    {
+        let mut remaining = factor;
+        let mut passes = vec![];
+        for &pass_factor in self.pass_factors()
+        {
+            while remaining > 1 && remaining.is_multiple_of(pass_factor)
+            {
+                passes.push(pass_factor);
+                remaining /= pass_factor;
+            }
+        }
+        (remaining == 1).then_some(passes)
+    }
+}
+
+/// Upscales the image to exactly `new_width`x`new_height`. Returns `None` unless that's the same whole number factor on both axes,
+/// and the factor can be made from the scaler's passes (2 and 3 for EPX, powers of 2 for the others)
+pub fn upscale(img: &DynamicImage, scaler: PixelScaler, new_width: u32, new_height: u32) -> Option<DynamicImage>
+{
+    let (width, height) = (img.width(), img.height());
+    if width == 0 || height == 0 || !new_width.is_multiple_of(width) || !new_height.is_multiple_of(height) || new_width / width != new_height / height
+    {
+        return None;
+    }
+
+    let mut scaled = img.to_rgba8();
+    for pass_factor in scaler.passes_for(new_width / width)?
+    {
+        scaled = match (scaler, pass_factor) {
+            (PixelScaler::Epx, 3) => scale3x(&scaled),
+            (PixelScaler::Epx, _) => scale2x(&scaled),
+            (PixelScaler::HqxLite, _) => hqx_lite2x(&scaled),
+            (PixelScaler::Xbr, _) => xbr2x(&scaled)
+        };
+    }
+    Some(DynamicImage::ImageRgba8(scaled))
+}
+
+/// Gets the pixel at an offset from (`x`, `y`), clamping to the edges of the image
+#[inline]
+fn neighbour(img: &RgbaImage, x: u32, y: u32, dx: i64, dy: i64) -> Rgba<u8>
+{
+    let nx = (x as i64 + dx).clamp(0, img.width() as i64 - 1);
+    let ny = (y as i64 + dy).clamp(0, img.height() as i64 - 1);
+    *img.get_pixel(nx as u32, ny as u32)
+}
+
+fn scale2x(img: &RgbaImage) -> RgbaImage
+{
+    let mut out = RgbaImage::new(img.width() * 2, img.height() * 2);
+    for (x, y, &e) in img.enumerate_pixels()
+    {
+        let b = neighbour(img, x, y, 0, -1);
+        let d = neighbour(img, x, y, -1, 0);
+        let f = neighbour(img, x, y, 1, 0);
+        let h = neighbour(img, x, y, 0, 1);
+
+        let mut block = [e; 4];
+        if b != h && d != f
+        {
+            if d == b { block[0] = d; }
+            if b == f { block[1] = f; }
+            if d == h { block[2] = d; }
+            if h == f { block[3] = f; }
+        }
+
+        for (i, &px) in block.iter().enumerate()
+        {
+            out.put_pixel(x * 2 + i as u32 % 2, y * 2 + i as u32 / 2, px);
+        }
+    }
+    out
+}
+
+fn scale3x(img: &RgbaImage) -> RgbaImage
+{
+    let mut out = RgbaImage::new(img.width() * 3, img.height() * 3);
+    for (x, y, &e) in img.enumerate_pixels()
+    {
+        let a = neighbour(img, x, y, -1, -1);
+        let b = neighbour(img, x, y, 0, -1);
+        let c = neighbour(img, x, y, 1, -1);
+        let d = neighbour(img, x, y, -1, 0);
+        let f = neighbour(img, x, y, 1, 0);
+        let g = neighbour(img, x, y, -1, 1);
+        let h = neighbour(img, x, y, 0, 1);
+        let i = neighbour(img, x, y, 1, 1);
+
+        let mut block = [e; 9];
+        if b != h && d != f
+        {
+            if d == b { block[0] = d; }
+            if (d == b && e != c) || (b == f && e != a) { block[1] = b; }
+            if b == f { block[2] = f; }
+            if (d == b && e != g) || (d == h && e != a) { block[3] = d; }
+            if (b == f && e != i) || (h == f && e != c) { block[5] = f; }
+            if d == h { block[6] = d; }
+            if (d == h && e != i) || (h == f && e != g) { block[7] = h; }
+            if h == f { block[8] = f; }
+        }
+
+        for (idx, &px) in block.iter().enumerate()
+        {
+            out.put_pixel(x * 3 + idx as u32 % 3, y * 3 + idx as u32 / 3, px);
+        }
+    }
+    out
+}
+
+/// Converts a pixel to (Y, U, V, A) the same way hqx does
+#[inline]
+fn to_yuva(px: Rgba<u8>) -> [f32; 4]
+{
+    let [r, g, b, a] = px.0.map(|c| c as f32);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.169 * r - 0.331 * g + 0.5 * b + 128.0,
+        0.5 * r - 0.419 * g - 0.081 * b + 128.0,
+        a
+    ]
+}
+
+/// The thresholds used by hqx to decide whether two colors are different
+const YUV_THRESHOLDS: [f32; 4] = [48.0, 7.0, 6.0, 48.0];
+
+#[inline]
+fn is_different(p1: Rgba<u8>, p2: Rgba<u8>) -> bool
+{
+    if p1.0[3] == 0 && p2.0[3] == 0
+    {
+        return false;
+    }
+    let (c1, c2) = (to_yuva(p1), to_yuva(p2));
+    (0..4).any(|i| (c1[i] - c2[i]).abs() > YUV_THRESHOLDS[i])
+}
+
+/// Weighted distance between two colors, used by xBR
+#[inline]
+fn distance(p1: Rgba<u8>, p2: Rgba<u8>) -> f32
+{
+    if p1.0[3] == 0 && p2.0[3] == 0
+    {
+        return 0.0;
+    }
+    let (c1, c2) = (to_yuva(p1), to_yuva(p2));
+    (0..4).map(|i| (c1[i] - c2[i]).abs() * YUV_THRESHOLDS[i]).sum()
+}
+
+/// Blends the colors by weight, with premultiplied alpha
+fn blend(colors: &[(Rgba<u8>, u32)]) -> Rgba<u8>
+{
+    let mut premultiplied = [0u32; 4];
+    let mut total_weight = 0;
+    for (px, weight) in colors
+    {
+        let alpha = px.0[3] as u32;
+        for (sum, &channel) in premultiplied.iter_mut().zip(&px.0[..3])
+        {
+            *sum += channel as u32 * alpha * weight;
+        }
+        premultiplied[3] += alpha * weight;
+        total_weight += weight;
+    }
+
+    if premultiplied[3] == 0
+    {
+        return Rgba([0, 0, 0, 0]);
+    }
+    Rgba([
+        (premultiplied[0] / premultiplied[3]) as u8,
+        (premultiplied[1] / premultiplied[3]) as u8,
+        (premultiplied[2] / premultiplied[3]) as u8,
+        (premultiplied[3] / total_weight) as u8
+    ])
+}
+
+/// The directions of the four output sub-pixels of a 2x upscale: top-left, top-right, bottom-left, bottom-right
+const CORNERS: [(i64, i64); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+fn hqx_lite2x(img: &RgbaImage) -> RgbaImage
+{
+    let mut out = RgbaImage::new(img.width() * 2, img.height() * 2);
+    for (x, y, &e) in img.enumerate_pixels()
+    {
+        for (cx, cy) in CORNERS
+        {
+            let horizontal = neighbour(img, x, y, cx, 0);
+            let vertical = neighbour(img, x, y, 0, cy);
+            let diagonal = neighbour(img, x, y, cx, cy);
+
+            let px = if !is_different(horizontal, vertical) && is_different(e, horizontal)
+            {
+                // the corner lies on an edge running across the diagonal
+                blend(&[(e, 2), (horizontal, 1), (vertical, 1)])
+            }
+            else if is_different(e, diagonal) && !is_different(e, horizontal) && !is_different(e, vertical)
+            {
+                // only the diagonal neighbour differs, so soften the corner slightly
+                blend(&[(e, 3), (diagonal, 1)])
+            }
+            else
+            {
+                e
+            };
+            out.put_pixel(x * 2 + (cx > 0) as u32, y * 2 + (cy > 0) as u32, px);
+        }
+    }
+    out
+}
+
+fn xbr2x(img: &RgbaImage) -> RgbaImage
+{
+    let mut out = RgbaImage::new(img.width() * 2, img.height() * 2);
+    for (x, y, &e) in img.enumerate_pixels()
+    {
+        for (cx, cy) in CORNERS
+        {
+            // the pattern is written for the bottom-right corner, and mirrored for the others
+            let at = |dx: i64, dy: i64| neighbour(img, x, y, dx * cx, dy * cy);
+            let (f, h, i) = (at(1, 0), at(0, 1), at(1, 1));
+
+            let weight_edge = distance(e, at(1, -1)) + distance(e, at(-1, 1)) + distance(i, at(2, 0)) + distance(i, at(0, 2)) + 4.0 * distance(h, f);
+            let weight_cross = distance(h, at(-1, 0)) + distance(h, at(1, 2)) + distance(f, at(2, 1)) + distance(f, at(0, -1)) + 4.0 * distance(e, i);
+
+            let px = if weight_edge < weight_cross
+            {
+                let new_color = if distance(e, f) <= distance(e, h) { f } else { h };
+                blend(&[(e, 1), (new_color, 1)])
+            }
+            else
+            {
+                e
+            };
+            out.put_pixel(x * 2 + (cx > 0) as u32, y * 2 + (cy > 0) as u32, px);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const GRAY: Rgba<u8> = Rgba([127, 127, 127, 255]);
+
+    /// Builds an image from rows of `#` (black), `.` (white) and `+` (the halfway gray)
+    fn from_art(rows: &[&str]) -> RgbaImage
+    {
+        RgbaImage::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| match rows[y as usize].as_bytes()[x as usize] {
+            b'#' => BLACK,
+            b'+' => GRAY,
+            _ => WHITE
+        })
+    }
+
+    /// A 4x4 staircase, black below the anti-diagonal
+    fn staircase() -> RgbaImage
+    {
+        from_art(&["...#", "..##", ".###", "####"])
+    }
+
+    #[test]
+    fn scale2x_smooths_a_diagonal()
+    {
+        let expected = from_art(&[
+            "......##",
+            ".....###",
+            ".....###",
+            "...#####",
+            "...#####",
+            ".#######",
+            "########",
+            "########"
+        ]);
+        assert_eq!(scale2x(&staircase()), expected);
+    }
+
+    #[test]
+    fn scale3x_smooths_a_diagonal()
+    {
+        let expected = from_art(&[
+            ".........###",
+            "........####",
+            "........####",
+            ".......#####",
+            "......######",
+            ".....#######",
+            "....########",
+            "...#########",
+            ".###########",
+            "############",
+            "############",
+            "############"
+        ]);
+        assert_eq!(scale3x(&staircase()), expected);
+    }
+
+    #[test]
+    fn xbr_blends_along_a_diagonal()
+    {
+        let expected = from_art(&[
+            "......##",
+            ".....+##",
+            "....+###",
+            "...+####",
+            "..+#####",
+            ".+######",
+            "########",
+            "########"
+        ]);
+        assert_eq!(xbr2x(&staircase()), expected);
+    }
+
+    #[test]
+    fn xbr_keeps_a_checkerboard_sharp()
+    {
+        // every diagonal is as strong as the one crossing it, so there's no edge to blend along
+        let checkerboard = from_art(&["#.#.", ".#.#", "#.#.", ".#.#"]);
+        let expected = from_art(&[
+            "##..##..",
+            "##..##..",
+            "..##..##",
+            "..##..##",
+            "##..##..",
+            "##..##..",
+            "..##..##",
+            "..##..##"
+        ]);
+        assert_eq!(xbr2x(&checkerboard), expected);
+    }
+
+    #[test]
+    fn hqx_lite_blends_corners_on_an_edge()
+    {
+        let img = from_art(&["#.", ".."]);
+        let scaled = hqx_lite2x(&img);
+        // the black pixel's inner corner sits on the edge, so it's blended, while its outer corners stay black
+        assert_eq!(*scaled.get_pixel(1, 1), blend(&[(BLACK, 2), (WHITE, 1), (WHITE, 1)]));
+        assert_eq!(*scaled.get_pixel(0, 0), BLACK);
+        assert_eq!(*scaled.get_pixel(3, 3), WHITE);
+    }
+
+    #[test]
+    fn upscale_only_accepts_reachable_factors()
+    {
+        let img = DynamicImage::ImageRgba8(staircase());
+        assert_eq!(upscale(&img, PixelScaler::Epx, 24, 24).map(|i| i.width()), Some(24));
+        assert_eq!(upscale(&img, PixelScaler::Xbr, 16, 16).map(|i| i.width()), Some(16));
+        assert!(upscale(&img, PixelScaler::Xbr, 12, 12).is_none());
+        assert!(upscale(&img, PixelScaler::Epx, 20, 20).is_none());
+        assert!(upscale(&img, PixelScaler::Epx, 8, 12).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+//! Finds the separate sprites on a spritesheet that has no XML, so they can be added as frames without selecting each one by hand
+
+use image::RgbaImage;
+
+/// A detected sprite's bounding box
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteRect
+{
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl SpriteRect
+{
+    fn right(&self) -> u32
+    {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> u32
+    {
+        self.y + self.height
+    }
+
+    /// Number of empty pixels between the two rects along each axis (0 if they touch or overlap on that axis)
+    fn gap(&self, other: &SpriteRect) -> (u32, u32)
+    {
+        let gap_x = other.x.saturating_sub(self.right()).max(self.x.saturating_sub(other.right()));
+        let gap_y = other.y.saturating_sub(self.bottom()).max(self.y.saturating_sub(other.bottom()));
+        (gap_x, gap_y)
+    }
+
+    fn union(&self, other: &SpriteRect) -> SpriteRect
+    {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        SpriteRect { x, y, width: self.right().max(other.right()) - x, height: self.bottom().max(other.bottom()) - y }
+    }
+}
+
+/// Used when no alpha threshold is given. Groups that are only made of pixels this faint are dust or leftover shadows, not sprites
+pub const DEFAULT_ALPHA_THRESHOLD: u8 = 32;
+
+/// Finds every group of connected pixels (diagonals included) that aren't fully transparent, and keeps the ones with at least one pixel
+/// with an alpha above `alpha_threshold`. Faint pixels still join the group they touch, so soft edges aren't cut off.
+/// Groups whose bounding boxes are at most `merge_distance` pixels apart are counted as the same sprite, so that detached bits
+/// (sparkles, speed lines, ...) stay with the sprite they belong to.
+///
+/// The sprites are returned in reading order: row by row from the top, and left to right within a row
+pub fn detect_sprites(img: &RgbaImage, alpha_threshold: u8, merge_distance: u32) -> Vec<SpriteRect>
+{
+    let (width, height) = img.dimensions();
+    let alpha = |x: u32, y: u32| img.get_pixel(x, y).0[3];
+    let mut visited = vec![false; (width * height) as usize];
+    let mut rects = vec![];
+
+    // flood fill every unvisited pixel, tracking the bounding box of what gets filled
+    let mut stack = vec![];
+    for start_y in 0..height
+    {
+        for start_x in 0..width
+        {
+            if visited[(start_y * width + start_x) as usize] || alpha(start_x, start_y) == 0
+            {
+                continue;
+            }
+
+            let (mut left, mut top, mut right, mut bottom) = (start_x, start_y, start_x, start_y);
+            let mut max_alpha = 0;
+            visited[(start_y * width + start_x) as usize] = true;
+            stack.push((start_x, start_y));
+            while let Some((x, y)) = stack.pop()
+            {
+                left = left.min(x);
+                right = right.max(x);
+                top = top.min(y);
+                bottom = bottom.max(y);
+                max_alpha = max_alpha.max(alpha(x, y));
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1)
+                {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(width - 1)
+                    {
+                        let i = (ny * width + nx) as usize;
+                        if !visited[i] && alpha(nx, ny) != 0
+                        {
+                            visited[i] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+            if max_alpha > alpha_threshold
+            {
+                rects.push(SpriteRect { x: left, y: top, width: right - left + 1, height: bottom - top + 1 });
+            }
+        }
+    }
+
+    reading_order(merge_close_rects(rects, merge_distance))
+}
+
+/// Merges rects that are within `merge_distance` of each other (overlapping ones always merge), until no two are that close.
+///
+/// Each pass sweeps the rects from left to right, so only rects that could be close along x get compared, and joins close ones with a union-find.
+/// A merged rect is bigger than its parts, so it can end up close to another one. Passes repeat until nothing merges
+fn merge_close_rects(mut rects: Vec<SpriteRect>, merge_distance: u32) -> Vec<SpriteRect>
+{
+    loop
+    {
+        let count = rects.len();
+        rects.sort_by_key(|rect| rect.x);
+        let mut parents: Vec<usize> = (0..count).collect();
+        for i in 0..count
+        {
+            let reach = rects[i].right().saturating_add(merge_distance);
+            for j in i + 1..count
+            {
+                // sorted by x, so every rect from here on is too far to the right
+                if rects[j].x > reach
+                {
+                    break;
+                }
+                let (gap_x, gap_y) = rects[i].gap(&rects[j]);
+                if gap_x <= merge_distance && gap_y <= merge_distance
+                {
+                    let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                    parents[root_j] = root_i;
+                }
+            }
+        }
+
+        let mut groups: Vec<Option<SpriteRect>> = vec![None; count];
+        for (i, rect) in rects.iter().enumerate()
+        {
+            let root = find_root(&mut parents, i);
+            groups[root] = Some(groups[root].map_or(*rect, |group| group.union(rect)));
+        }
+        rects = groups.into_iter().flatten().collect();
+        if rects.len() == count
+        {
+            return rects;
+        }
+    }
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize
+{
+    while parents[i] != i
+    {
+        // path halving keeps the trees flat
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Sorts rects into rows (a rect joins a row if its vertical center is within the row's first rect), then left to right
+fn reading_order(mut rects: Vec<SpriteRect>) -> Vec<SpriteRect>
+{
+    rects.sort_by_key(|rect| (rect.y, rect.x));
+    let mut rows: Vec<(SpriteRect, Vec<SpriteRect>)> = vec![];
+    for rect in rects
+    {
+        let center_y = rect.y + rect.height / 2;
+        match rows.iter_mut().find(|(first, _)| center_y >= first.y && center_y < first.bottom()) {
+            Some((_, row)) => row.push(rect),
+            None => rows.push((rect, vec![rect]))
+        }
+    }
+
+    rows.into_iter()
+        .flat_map(|(_, mut row)| {
+            row.sort_by_key(|rect| rect.x);
+            row
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use image::Rgba;
+
+    use super::*;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> SpriteRect
+    {
+        SpriteRect { x, y, width, height }
+    }
+
+    /// A transparent image with every rect filled in with the given alpha
+    fn sheet(width: u32, height: u32, filled: &[(SpriteRect, u8)]) -> RgbaImage
+    {
+        let mut img = RgbaImage::new(width, height);
+        for (rect, alpha) in filled
+        {
+            for y in rect.y..rect.bottom()
+            {
+                for x in rect.x..rect.right()
+                {
+                    img.put_pixel(x, y, Rgba([255, 0, 0, *alpha]));
+                }
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn sprites_come_in_reading_order()
+    {
+        // two rows; the second sprite of the top row is taller and starts higher, but is still on the same row
+        let img = sheet(20, 14, &[
+            (rect(10, 1, 3, 5), 255),
+            (rect(1, 2, 4, 3), 255),
+            (rect(15, 2, 2, 2), 255),
+            (rect(6, 9, 3, 3), 255),
+            (rect(0, 10, 2, 2), 255)
+        ]);
+        assert_eq!(detect_sprites(&img, 0, 0), vec![
+            rect(1, 2, 4, 3),
+            rect(10, 1, 3, 5),
+            rect(15, 2, 2, 2),
+            rect(0, 10, 2, 2),
+            rect(6, 9, 3, 3)
+        ]);
+    }
+
+    #[test]
+    fn diagonal_pixels_are_connected()
+    {
+        let img = sheet(6, 6, &[(rect(1, 1, 2, 2), 255), (rect(3, 3, 2, 2), 255)]);
+        assert_eq!(detect_sprites(&img, 0, 0), vec![rect(1, 1, 4, 4)]);
+    }
+
+    #[test]
+    fn close_groups_merge()
+    {
+        let img = sheet(20, 8, &[(rect(0, 0, 4, 4), 255), (rect(6, 0, 2, 2), 255), (rect(15, 0, 4, 4), 255)]);
+        assert_eq!(detect_sprites(&img, 0, 1).len(), 3);
+        assert_eq!(detect_sprites(&img, 0, 2), vec![rect(0, 0, 8, 4), rect(15, 0, 4, 4)]);
+    }
+
+    #[test]
+    fn merged_rects_keep_merging()
+    {
+        // the middle sparkle is only close to the right sprite once the left two have merged into one rect
+        let img = sheet(20, 12, &[(rect(0, 0, 3, 10), 255), (rect(4, 0, 2, 2), 255), (rect(7, 8, 3, 3), 255)]);
+        assert_eq!(detect_sprites(&img, 0, 1), vec![rect(0, 0, 10, 11)]);
+    }
+
+    #[test]
+    fn faint_groups_are_skipped_but_faint_edges_are_kept()
+    {
+        let img = sheet(16, 8, &[
+            // a sprite with a soft edge
+            (rect(1, 1, 4, 4), 255),
+            (rect(5, 1, 1, 4), 10),
+            // dust
+            (rect(10, 1, 2, 2), 10)
+        ]);
+        assert_eq!(detect_sprites(&img, DEFAULT_ALPHA_THRESHOLD, 0), vec![rect(1, 1, 5, 4)]);
+        assert_eq!(detect_sprites(&img, 0, 0), vec![rect(1, 1, 5, 4), rect(10, 1, 2, 2)]);
+    }
+
+    #[test]
+    fn empty_images_have_no_sprites()
+    {
+        assert!(detect_sprites(&RgbaImage::new(8, 8), 0, 4).is_empty());
+        assert!(detect_sprites(&RgbaImage::new(0, 0), 0, 0).is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod export;
 pub mod growingpacker;
 
 mod helpers {
@@ -0,0 +1,229 @@
+//! The result of packing a spritesheet, with every part of it available separately so JS doesn't have to unzip anything
+
+use std::io::{self, Write};
+
+use wasm_bindgen::prelude::*;
+
+use crate::quantize::QuantizationReport;
+
+/// Where a single frame ended up on the spritesheet, the same as its `SubTexture` in the XML
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct FramePlacement
+{
+    name: String,
+    /// Name of the animation the frame belongs to
+    animation_prefix: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub frame_x: i32,
+    pub frame_y: i32,
+    pub frame_width: u32,
+    pub frame_height: u32
+}
+
+#[wasm_bindgen]
+impl FramePlacement
+{
+    /// The full `SubTexture` name, e.g. `idle0003`
+    pub fn name(&self) -> String
+    {
+        self.name.clone()
+    }
+
+    pub fn animation_prefix(&self) -> String
+    {
+        self.animation_prefix.clone()
+    }
+}
+
+impl FramePlacement
+{
+    pub fn from_subtexture(name: String, subtexture: &crate::textureatlas_format::SubTexture) -> Self
+    {
+        Self {
+            name,
+            animation_prefix: subtexture.name.clone(),
+            x: subtexture.x,
+            y: subtexture.y,
+            width: subtexture.width,
+            height: subtexture.height,
+            frame_x: subtexture.frame_x.unwrap_or(0),
+            frame_y: subtexture.frame_y.unwrap_or(0),
+            frame_width: subtexture.frame_width.unwrap_or(subtexture.width),
+            frame_height: subtexture.frame_height.unwrap_or(subtexture.height)
+        }
+    }
+}
+
+/// Numbers describing a packed spritesheet
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackStats
+{
+    /// Size of the spritesheet image (GPU textures may be padded a bit more)
+    pub width: u32,
+    pub height: u32,
+    /// Number of `SubTexture`s in the XML
+    pub frame_count: u32,
+    /// Number of distinct images packed (frames that look the same share one)
+    pub unique_images: u32,
+    /// Number of frames that are fully transparent
+    pub empty_frames: u32,
+    /// Fraction of the spritesheet covered by packed images, from 0 to 1
+    pub occupancy: f32
+}
+
+/// One spritesheet image and its XML, at a single scale
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct PackedAtlas
+{
+    pub(crate) name: String,
+    pub scale: f32,
+    pub(crate) image_extension: &'static str,
+    pub(crate) image_bytes: Vec<u8>,
+    pub(crate) xml: String,
+    pub(crate) placements: Vec<FramePlacement>,
+    pub stats: PackStats,
+    pub(crate) quantization_report: Option<QuantizationReport>
+}
+
+#[wasm_bindgen]
+impl PackedAtlas
+{
+    /// The file name without an extension, e.g. `bf@0.5x`
+    pub fn name(&self) -> String
+    {
+        self.name.clone()
+    }
+
+    pub fn image_file_name(&self) -> String
+    {
+        format!("{}{}", self.name, self.image_extension)
+    }
+
+    pub fn xml_file_name(&self) -> String
+    {
+        format!("{}.xml", self.name)
+    }
+
+    /// The encoded spritesheet image (PNG unless a texture output was chosen)
+    pub fn image_bytes(&self) -> Vec<u8>
+    {
+        self.image_bytes.clone()
+    }
+
+    pub fn xml(&self) -> String
+    {
+        self.xml.clone()
+    }
+
+    pub fn placement_count(&self) -> usize
+    {
+        self.placements.len()
+    }
+
+    /// Placements are in the same order as the `SubTexture`s in the XML
+    pub fn placement(&self, index: usize) -> Option<FramePlacement>
+    {
+        self.placements.get(index).cloned()
+    }
+
+    /// Only available when indexed output is on
+    pub fn quantization_report(&self) -> Option<QuantizationReport>
+    {
+        self.quantization_report
+    }
+}
+
+/// Everything `make_export` produced: the spritesheets at every scale, plus any extra files (character files, manifest)
+#[wasm_bindgen]
+pub struct PackedExport
+{
+    /// Folder (inside the zip) the spritesheets go in
+    image_folder: String,
+    atlases: Vec<PackedAtlas>,
+    extra_files: Vec<(String, Vec<u8>)>
+}
+
+#[wasm_bindgen]
+impl PackedExport
+{
+    pub fn atlas_count(&self) -> usize
+    {
+        self.atlases.len()
+    }
+
+    /// Atlases are in the same order as the scales they were made with
+    pub fn atlas(&self, index: usize) -> Option<PackedAtlas>
+    {
+        self.atlases.get(index).cloned()
+    }
+
+    /// Number of files that `to_zip` writes
+    pub fn file_count(&self) -> usize
+    {
+        self.atlases.len() * 2 + self.extra_files.len()
+    }
+
+    /// Path of a file inside the zip
+    pub fn file_path(&self, index: usize) -> Option<String>
+    {
+        self.files().into_iter().nth(index).map(|(path, _)| path)
+    }
+
+    pub fn file_bytes(&self, index: usize) -> Option<Vec<u8>>
+    {
+        self.files().into_iter().nth(index).map(|(_, bytes)| bytes.to_vec())
+    }
+
+    /// Zips up every file, ready to be extracted
+    pub fn to_zip(&self) -> Vec<u8>
+    {
+        let mut zip_buf: Vec<u8> = Vec::new();
+        let zipcursor = io::Cursor::new(&mut zip_buf);
+
+        let mut zip_writer = zip::ZipWriter::new(zipcursor);
+        let zip_opts = zip::write::FileOptions::default();
+
+        for (path, bytes) in self.files()
+        {
+            zip_writer.start_file(path, zip_opts).expect("Could not write to zip!");
+            zip_writer.write_all(bytes).expect("Zip error!");
+        }
+
+        zip_writer.finish().expect("Error finising zip!");
+        drop(zip_writer);
+
+        zip_buf
+    }
+}
+
+impl PackedExport
+{
+    pub fn new(image_folder: String, atlases: Vec<PackedAtlas>) -> Self
+    {
+        Self { image_folder, atlases, extra_files: vec![] }
+    }
+
+    pub fn add_file(&mut self, path: String, bytes: Vec<u8>)
+    {
+        self.extra_files.push((path, bytes));
+    }
+
+    /// Every file along with its path in the zip: the image and XML of each atlas, then the extra files
+    pub fn files(&self) -> Vec<(String, &[u8])>
+    {
+        let mut files = Vec::with_capacity(self.file_count());
+        for atlas in &self.atlases
+        {
+            files.push((format!("{}{}", self.image_folder, atlas.image_file_name()), atlas.image_bytes.as_slice()));
+            files.push((format!("{}{}", self.image_folder, atlas.xml_file_name()), atlas.xml.as_bytes()));
+        }
+        files.extend(self.extra_files.iter().map(|(path, bytes)| (path.clone(), bytes.as_slice())));
+        files
+    }
+}
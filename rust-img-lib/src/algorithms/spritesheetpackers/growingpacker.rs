@@ -1,9 +1,11 @@
-use std::{collections::HashMap, io::{self, Write}};
+use std::collections::HashMap;
 
 use wasm_bindgen::prelude::*;
 
-use crate::{utils::{PackError, encode_image_as_png, self, transform_image, pad_image_uniform, PrefixCounter}, algorithms::{PackingRectangle, Packer, FitRect}, textureatlas_format::{self, SubTexture}};
+use crate::{utils::{PackError, encode_image_as_png, encode_image_as_png_with_report, PngOptions, ColorOp, ImageEffect, BackgroundRemoval, self, transform_image, TransformError, pad_image_uniform, PrefixCounter}, algorithms::{PackingRectangle, Packer, FitRect, pixelscalers::PixelScaler, blockcompression::BlockCompression, spritedetection::{self, SpriteRect}}, textureatlas_format::{self, SubTexture}, quantize::{Quantization, QuantizationReport}, pngwriter::{PngCompression, PngFilter}, texturewriter::{self, GpuTextureOptions, TextureContainer}, bitdepth::{self, SixteenBitFormat, SixteenBitOptions, SixteenBitContainer, Dithering}, modbundle::{self, ZipLayout}, progress::{CancellationToken, Cancelled, ProgressReporter}, animpreview::{self, AnimationPreviewOptions}, framerender::{self, RenderedFrame}, zipimport::{self, ZipImportOptions}};
+use super::export::{FramePlacement, PackStats, PackedAtlas, PackedExport};
 use image::{imageops, DynamicImage};
+use serde_json::json;
 use super::helpers;
 
 // use super::{PackingRectangle, Packer, FitRect, growingpack_fns};
@@ -13,9 +15,297 @@ pub struct TransformInfo
     pub new_width: u32,
     pub new_height: u32,
     pub flip_x: bool,
-    pub flip_y: bool
+    pub flip_y: bool,
+    /// Clockwise rotation in degrees, applied after scaling and flipping
+    pub rotation: f32,
+    pub filter: ResampleFilter,
+    /// Applied before any of the other transforms
+    pub color_ops: Vec<ColorOp>,
+    /// Applied after all of the other transforms
+    pub effects: Vec<ImageEffect>
 }
 
+/// The filter used when a frame has to be resized
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResampleFilter
+{
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+    /// Nearest neighbour, but only whole number upscales are allowed (for pixel art)
+    IntegerNearest,
+    /// Scale2x/Scale3x pixel art upscaling. Upscales must be by the same whole factor on both axes, made of 2s and 3s (2, 3, 4, 6, 8, 9, ...). 
+    /// Downscales use nearest neighbour
+    Epx,
+    /// A simplified take on hq2x (not the full hqx pattern table). Upscales must be by the same power of 2 on both axes. Downscales use nearest neighbour
+    HqxLite,
+    /// xBR (level 1) pixel art upscaling. Upscales must be by the same power of 2 on both axes. Downscales use nearest neighbour
+    Xbr
+}
+
+impl ResampleFilter
+{
+    /// The pixel art upscaler for this filter, if it is one
+    pub fn pixel_scaler(&self) -> Option<PixelScaler>
+    {
+        match self {
+            ResampleFilter::Epx => Some(PixelScaler::Epx),
+            ResampleFilter::HqxLite => Some(PixelScaler::HqxLite),
+            ResampleFilter::Xbr => Some(PixelScaler::Xbr),
+            _ => None
+        }
+    }
+}
+
+impl From<ResampleFilter> for imageops::FilterType
+{
+    fn from(filter: ResampleFilter) -> Self {
+        match filter {
+            ResampleFilter::Nearest 
+            | ResampleFilter::IntegerNearest 
+            | ResampleFilter::Epx 
+            | ResampleFilter::HqxLite 
+            | ResampleFilter::Xbr => imageops::FilterType::Nearest,
+            ResampleFilter::Triangle => imageops::FilterType::Triangle,
+            ResampleFilter::CatmullRom => imageops::FilterType::CatmullRom,
+            ResampleFilter::Gaussian => imageops::FilterType::Gaussian,
+            ResampleFilter::Lanczos3 => imageops::FilterType::Lanczos3
+        }
+    }
+}
+
+/// A list of color operations, built up from JS and attached to frames or whole animations
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct ColorOps
+{
+    ops: Vec<ColorOp>
+}
+
+#[wasm_bindgen]
+impl ColorOps
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Maps every color in `from` to the color at the same index in `to`. Colors are given as `0xRRGGBB`.
+    /// Returns an error (without adding anything) if the lists aren't the same length
+    pub fn palette_swap(&mut self, from: Vec<u32>, to: Vec<u32>) -> Result<(), JsError>
+    {
+        if from.len() != to.len()
+        {
+            return Err(JsError::new(&format!("Palette swap needs the same number of colors on both sides, got {} and {}", from.len(), to.len())));
+        }
+        let palette = from
+            .into_iter()
+            .zip(to)
+            .map(|(from_color, to_color)| (rgb_from_u32(from_color), rgb_from_u32(to_color)))
+            .collect();
+        self.ops.push(ColorOp::PaletteMap(palette));
+        Ok(())
+    }
+
+    /// Rotates the hue by `hue` degrees, and adds `saturation` and `value` (both between -1 and 1)
+    pub fn hsv_shift(&mut self, hue: f32, saturation: f32, value: f32)
+    {
+        self.ops.push(ColorOp::HsvShift { hue, saturation, value });
+    }
+
+    /// Multiplies every pixel by `color` (given as `0xRRGGBB`)
+    pub fn tint(&mut self, color: u32)
+    {
+        self.ops.push(ColorOp::Tint(rgb_from_u32(color)));
+    }
+}
+
+/// The thickest outline `FrameEffects::outline` accepts, in pixels
+const MAX_OUTLINE_THICKNESS: u32 = 64;
+/// The largest blur (the gaussian's sigma) `FrameEffects::drop_shadow` accepts
+const MAX_SHADOW_BLUR: f32 = 64.0;
+/// The furthest `FrameEffects::drop_shadow` can move the shadow on either axis, in pixels
+const MAX_SHADOW_OFFSET: i32 = 1024;
+
+/// A list of effects (applied in order), built up from JS and attached to frames or whole animations
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct FrameEffects
+{
+    effects: Vec<ImageEffect>
+}
+
+#[wasm_bindgen]
+impl FrameEffects
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Adds a solid outline, up to 64 pixels thick. `color` is given as `0xRRGGBBAA`.
+    /// Returns an error (without adding anything) if it's any thicker
+    pub fn outline(&mut self, thickness: u32, color: u32) -> Result<(), JsError>
+    {
+        if thickness > MAX_OUTLINE_THICKNESS
+        {
+            return Err(JsError::new(&format!("The outline can be at most {} pixels thick, got {}", MAX_OUTLINE_THICKNESS, thickness)));
+        }
+        self.effects.push(ImageEffect::Outline { thickness, color: color.to_be_bytes() });
+        Ok(())
+    }
+
+    /// Adds a drop shadow, blurred by a gaussian with sigma `blur` (between 0 and 64), and moved by at most 1024 pixels on either axis. 
+    /// `color` is given as `0xRRGGBBAA`. Returns an error (without adding anything) if the blur or offset is out of range
+    pub fn drop_shadow(&mut self, offset_x: i32, offset_y: i32, blur: f32, color: u32) -> Result<(), JsError>
+    {
+        if !(0.0..=MAX_SHADOW_BLUR).contains(&blur)
+        {
+            return Err(JsError::new(&format!("The shadow's blur must be between 0 and {}, got {}", MAX_SHADOW_BLUR, blur)));
+        }
+        if offset_x.unsigned_abs() > MAX_SHADOW_OFFSET as u32 || offset_y.unsigned_abs() > MAX_SHADOW_OFFSET as u32
+        {
+            return Err(JsError::new(&format!("The shadow can be moved by at most {} pixels, got ({}, {})", MAX_SHADOW_OFFSET, offset_x, offset_y)));
+        }
+        self.effects.push(ImageEffect::DropShadow { offset_x, offset_y, blur, color: color.to_be_bytes() });
+        Ok(())
+    }
+
+    /// Fills the frame with a flat color. `color` is given as `0xRRGGBBAA`
+    pub fn silhouette(&mut self, color: u32)
+    {
+        self.effects.push(ImageEffect::Silhouette { color: color.to_be_bytes() });
+    }
+}
+
+/// Per-frame settings for `add_single_frame` and `add_spritesheet_frame`. Passed by reference, so the same options can be used for any number of frames.
+/// `resample_filter` and `rotation` can be changed from JS after creating it with `new`
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct FrameOptions
+{
+    /// Overrides the packer's resample filter when the frame is resized
+    pub resample_filter: Option<ResampleFilter>,
+    /// Clockwise rotation in degrees, applied after scaling and flipping
+    pub rotation: f32,
+    color_ops: Vec<ColorOp>,
+    effects: Vec<ImageEffect>
+}
+
+#[wasm_bindgen]
+impl FrameOptions
+{
+    /// The packer's resample filter, no rotation, and no color operations or effects
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Sets the color operations applied to the frame (after the animation's own, see `set_animation_color_ops`)
+    pub fn set_color_ops(&mut self, color_ops: &ColorOps)
+    {
+        self.color_ops = color_ops.ops.clone();
+    }
+
+    /// Sets the effects applied to the frame (after the animation's own, see `set_animation_effects`)
+    pub fn set_effects(&mut self, effects: &FrameEffects)
+    {
+        self.effects = effects.effects.clone();
+    }
+}
+
+/// How a spritesheet is divided into equally sized cells, for `add_grid_frames`. Every field can be changed from JS after creating it with `new`
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct GridLayout
+{
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// Space around the whole grid, on both sides
+    pub margin_x: u32,
+    pub margin_y: u32,
+    /// Space between neighbouring cells
+    pub spacing_x: u32,
+    pub spacing_y: u32,
+    /// First row and column to slice, counting from 0
+    pub first_row: u32,
+    pub first_column: u32,
+    /// Number of rows and columns to slice. Every remaining one that fits if not set
+    pub row_count: Option<u32>,
+    pub column_count: Option<u32>
+}
+
+#[wasm_bindgen]
+impl GridLayout
+{
+    /// A grid with no margins or spacing, covering the whole image
+    pub fn new(cell_width: u32, cell_height: u32) -> Self
+    {
+        Self { cell_width, cell_height, margin_x: 0, margin_y: 0, spacing_x: 0, spacing_y: 0, first_row: 0, first_column: 0, row_count: None, column_count: None }
+    }
+}
+
+impl GridLayout
+{
+    /// Returns why the grid can't be used on a `width`x`height` image: its cells are empty, or not a single one fits
+    fn validate(&self, width: u32, height: u32) -> Result<(), String>
+    {
+        if self.cell_width == 0 || self.cell_height == 0
+        {
+            return Err(format!("Grid cells can't be empty, got {}x{}", self.cell_width, self.cell_height));
+        }
+        if self.fitting_columns(width) == 0 || self.fitting_rows(height) == 0
+        {
+            return Err(format!("Not a single {}x{} grid cell fits in the {}x{} spritesheet", self.cell_width, self.cell_height, width, height));
+        }
+        Ok(())
+    }
+
+    fn fitting_columns(&self, width: u32) -> u32
+    {
+        fitting_cells(width, self.margin_x, self.cell_width, self.spacing_x)
+    }
+
+    fn fitting_rows(&self, height: u32) -> u32
+    {
+        fitting_cells(height, self.margin_y, self.cell_height, self.spacing_y)
+    }
+
+    /// The (x, y) of every cell to slice from a `width`x`height` image, row by row. Cells that don't fully fit are left out
+    fn cells(&self, width: u32, height: u32) -> Vec<(u32, u32)>
+    {
+        let pick = |fitting: u32, first: u32, count: Option<u32>| first..count.map_or(fitting, |count| first.saturating_add(count).min(fitting));
+        let rows = pick(self.fitting_rows(height), self.first_row, self.row_count);
+        let columns = pick(self.fitting_columns(width), self.first_column, self.column_count);
+        // every cell fits, so its position does too, but the step between cells may not
+        let position = |margin: u32, index: u32, cell: u32, spacing: u32| (margin as u64 + index as u64 * (cell as u64 + spacing as u64)) as u32;
+        rows
+            .flat_map(|row| columns.clone().map(move |column| (column, row)))
+            .map(|(column, row)| (
+                position(self.margin_x, column, self.cell_width, self.spacing_x),
+                position(self.margin_y, row, self.cell_height, self.spacing_y)
+            ))
+            .collect()
+    }
+}
+
+/// How many cells fit along a side of `size` pixels. The same as tiled: the last cell needs no spacing after it, but does need the margin
+fn fitting_cells(size: u32, margin: u32, cell: u32, spacing: u32) -> u32
+{
+    ((size as u64 + spacing as u64).saturating_sub(2 * margin as u64) / (cell as u64 + spacing as u64)) as u32
+}
+
+#[inline]
+fn rgb_from_u32(color: u32) -> [u8; 3]
+{
+    let [_, r, g, b] = color.to_be_bytes();
+    [r, g, b]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct FrameRectInfo
 {
     frame_x: i64,
@@ -24,6 +314,83 @@ struct FrameRectInfo
     frame_height: u64
 }
 
+impl FrameRectInfo
+{
+    /// Scales, mirrors and rotates the frame rect of a `src_width`x`src_height` image, so that it matches the image after `transform_image` is applied.
+    /// 
+    /// Flips mirror the whole frame, so the image ends up where it would be if the entire frame had been flipped.
+    /// Rotations turn the image around the frame's center. Quarter turns turn the frame with it, any other angle keeps the frame's size.
+    /// Effects that grow the image keep it in place, and grow the frame by the same amount
+    fn transformed(&self, src_width: u32, src_height: u32, transform: &TransformInfo) -> FrameRectInfo
+    {
+        let (new_width, new_height) = (transform.new_width as i64, transform.new_height as i64);
+        let scale_x = if src_width > 0 { new_width as f64 / src_width as f64 } else { 1.0 };
+        let scale_y = if src_height > 0 { new_height as f64 / src_height as f64 } else { 1.0 };
+
+        let mut frame_x = (self.frame_x as f64 * scale_x).round() as i64;
+        let mut frame_y = (self.frame_y as f64 * scale_y).round() as i64;
+        let mut frame_width = (self.frame_width as f64 * scale_x).round() as u64;
+        let mut frame_height = (self.frame_height as f64 * scale_y).round() as u64;
+
+        if transform.flip_x
+        {
+            frame_x = new_width - frame_width as i64 - frame_x;
+        }
+        if transform.flip_y
+        {
+            frame_y = new_height - frame_height as i64 - frame_y;
+        }
+
+        if transform.rotation != 0.0
+        {
+            // rotate the image around the center of the frame (the pivot), so the frame's center stays put
+            let (rotated_width, rotated_height) = utils::rotated_dimensions(transform.new_width, transform.new_height, transform.rotation);
+            let (sin, cos) = (transform.rotation as f64).to_radians().sin_cos();
+            let center_x = -frame_x as f64 + new_width as f64 / 2.0 - frame_width as f64 / 2.0;
+            let center_y = -frame_y as f64 + new_height as f64 / 2.0 - frame_height as f64 / 2.0;
+            if let Some(1) | Some(3) = utils::as_quarter_turns(transform.rotation)
+            {
+                std::mem::swap(&mut frame_width, &mut frame_height);
+            }
+            let rotated_center_x = frame_width as f64 / 2.0 + center_x * cos - center_y * sin;
+            let rotated_center_y = frame_height as f64 / 2.0 + center_x * sin + center_y * cos;
+            frame_x = -(rotated_center_x - rotated_width as f64 / 2.0).round() as i64;
+            frame_y = -(rotated_center_y - rotated_height as f64 / 2.0).round() as i64;
+        }
+
+        // effects grow the image outwards, and the frame grows with it so that nothing they add gets cut off
+        for effect in &transform.effects
+        {
+            let (left, top, right, bottom) = effect.growth();
+            frame_width += (left + right) as u64;
+            frame_height += (top + bottom) as u64;
+        }
+
+        FrameRectInfo { frame_x, frame_y, frame_width, frame_height }
+    }
+
+    /// Scales the frame rect to match a frame image that was scaled by `scale`.
+    /// The `padding` around the image is not scaled
+    fn scaled(&self, scale: f32, padding: u32) -> FrameRectInfo
+    {
+        // the (unpadded) image sits at `padding - frame_x` inside the frame, and that's the position that gets scaled
+        let padding = padding as i64;
+        let scale_offset = |offset: i64| padding - ((padding - offset) as f64 * scale as f64).round() as i64;
+        FrameRectInfo {
+            frame_x: scale_offset(self.frame_x),
+            frame_y: scale_offset(self.frame_y),
+            frame_width: (self.frame_width as f64 * scale as f64).round() as u64,
+            frame_height: (self.frame_height as f64 * scale as f64).round() as u64
+        }
+    }
+}
+
+#[inline]
+fn scale_dimension(size: u32, scale: f32) -> u32
+{
+    ((size as f64 * scale as f64).round() as u32).max(1)
+}
+
 struct FrameInfo
 {
     // spr_id: String,
@@ -34,6 +401,15 @@ struct FrameInfo
     _index: usize
 }
 
+/// The frames of one animation, as listed in the bundle manifest
+struct AnimationSummary
+{
+    prefix: String,
+    frame_count: usize,
+    /// CRC32 of the trimmed pixels and frame rect of every frame, in order
+    crc: u32
+}
+
 struct ImageCache
 {
     cache: HashMap<u64, DynamicImage>,
@@ -46,43 +422,154 @@ impl ImageCache {
         Self { cache: HashMap::new(), _empty_img_hash: None }
     }
 
-    pub fn add_image(&mut self, img: DynamicImage, padding: u32, clip_to_bounding_box: bool) -> (u64, (i32, i32, u32, u32))
+    /// Trims, pads and caches the image.
+    /// 
+    /// Returns the hash of the cached image along with its bounds, or `None` if the image is fully transparent
+    pub fn add_image(&mut self, img: DynamicImage, padding: u32, clip_to_bounding_box: bool) -> Option<(u64, (i32, i32, u32, u32))>
     {
-        let bounds_opt = utils::get_bounding_box(&img, None);
-        match bounds_opt {
-            Some((left, top, right, bottom)) => {
-                let mut cropped_img = img;
-                if clip_to_bounding_box {
-                    cropped_img = cropped_img.crop_imm(left, top, right - left, bottom - top);
-                }
-                cropped_img = pad_image_uniform(cropped_img, padding);
-                let imghash = utils::get_hash_from_image_bytes(cropped_img.as_bytes());
-                if !self.cache.contains_key(&imghash)
-                {
-                    self.cache.insert(imghash, cropped_img);
-                }
-                return (imghash, ((left as i32 - padding as i32), (top as i32 - padding as i32), right + padding, bottom + padding));
-            }
+        let (left, top, right, bottom) = utils::get_bounding_box(&img, None)?;
+        let mut cropped_img = img;
+        if clip_to_bounding_box {
+            cropped_img = cropped_img.crop_imm(left, top, right - left, bottom - top);
+        }
+        cropped_img = pad_image_uniform(cropped_img, padding);
+        let imghash = utils::get_hash_from_image_bytes(cropped_img.as_bytes());
+        self.cache.entry(imghash).or_insert(cropped_img);
+        Some((imghash, ((left as i32 - padding as i32), (top as i32 - padding as i32), right + padding, bottom + padding)))
+    }
+
+    /// Caches a single transparent pixel (once) to stand in for empty frames
+    pub fn add_empty_pixel(&mut self) -> (u64, (i32, i32, u32, u32))
+    {
+        let hash = match self._empty_img_hash {
+            Some(hash) => hash,
             None => {
-                match self._empty_img_hash {
-                    None => {
-                        let img = image::DynamicImage::new_rgba8(4, 4);
-                        let empty_img_hash = utils::get_hash_from_image_bytes(img.as_bytes());
-                        if !self.cache.contains_key(&empty_img_hash)
-                        {
-                            self.cache.insert(empty_img_hash, img);
-                        }
-                        self._empty_img_hash = Some(empty_img_hash);
-                        return (empty_img_hash, (0, 0, 4, 4));
-                    },
-                    Some(hash) => {
-                        return (hash, (0, 0, 4, 4));
-                    }
-                }
+                let img = image::DynamicImage::new_rgba8(1, 1);
+                let empty_img_hash = utils::get_hash_from_image_bytes(img.as_bytes());
+                self.cache.entry(empty_img_hash).or_insert(img);
+                self._empty_img_hash = Some(empty_img_hash);
+                empty_img_hash
             }
+        };
+        (hash, (0, 0, 1, 1))
+    }
+}
+
+/// How fully transparent frames are written to the spritesheet
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyFrameMode
+{
+    /// A single transparent pixel is packed in place of the frame
+    Pixel,
+    /// A `SubTexture` with zero width and height (but the correct frameWidth/frameHeight) is written
+    ZeroSize,
+    /// The frame is removed from its animation
+    Drop
+}
+
+/// The format of the file listing every frame of an image sequence
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceManifest
+{
+    /// `frames.json`, with a `frames` array
+    Json,
+    /// `frames.csv`, with a header row
+    Csv
+}
+
+/// What the spritesheet images are written as
+#[derive(Clone, Copy, Debug)]
+enum AtlasOutput
+{
+    Png,
+    GpuTexture(GpuTextureOptions),
+    SixteenBit(SixteenBitOptions)
+}
+
+/// A spritesheet part way through being made by `export_step`
+struct AtlasJob
+{
+    name: String,
+    scale: f32,
+    /// Frame images resized to `scale` so far. Unused at scale 1, where the cached images are packed as they are
+    scaled_images: HashMap<u64, DynamicImage>,
+    /// Hashes of the cached images that still need resizing
+    to_scale: Vec<u64>,
+    /// The spritesheet and where every image goes on it, once packed
+    packed: Option<(DynamicImage, Vec<FitRect>)>,
+    /// Number of images drawn onto the spritesheet so far
+    drawn: usize
+}
+
+/// Frames to rebuild, as the hash of their cached image (`None` for empty frames) and their frame rect
+type FrameList = Vec<(Option<u64>, FrameRectInfo)>;
+
+/// What goes in a single file of an image sequence
+enum SequenceItem
+{
+    /// A frame, rebuilt from its cached image (`None` for empty frames) and frame rect
+    Frame(Option<u64>, FrameRectInfo),
+    /// A cached image, as-is
+    UniqueImage(u64),
+    /// An animated preview of the frames, given the same way as `Frame`
+    AnimationPreview(FrameList, AnimationPreviewOptions)
+}
+
+/// An export started with `start_export` or `start_img_sequence`
+enum ExportJob
+{
+    Spritesheets
+    {
+        scales: Vec<f32>,
+        image_folder: String,
+        atlases: Vec<PackedAtlas>,
+        current: Option<AtlasJob>
+    },
+    ImageSequence
+    {
+        /// Every file along with its path
+        items: Vec<(String, SequenceItem)>,
+        written: usize,
+        export: PackedExport
+    },
+    Finished(PackedExport)
+}
+
+/// Why an export stopped part way through
+#[derive(Debug)]
+enum ExportError
+{
+    Cancelled(Cancelled),
+    Pack(PackError)
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Cancelled(cancelled) => cancelled.fmt(f),
+            ExportError::Pack(err) => err.fmt(f)
         }
     }
 }
+impl std::error::Error for ExportError {}
+
+impl From<Cancelled> for ExportError
+{
+    fn from(cancelled: Cancelled) -> Self
+    {
+        ExportError::Cancelled(cancelled)
+    }
+}
+
+impl From<PackError> for ExportError
+{
+    fn from(err: PackError) -> Self
+    {
+        ExportError::Pack(err)
+    }
+}
 
 #[wasm_bindgen]
 pub struct GrowingPacker
@@ -91,6 +578,21 @@ pub struct GrowingPacker
     img_padding: u32,
     frame_image_cache: ImageCache,
     frames: HashMap<u64, Vec<FrameInfo>>,
+    empty_frames: Vec<FrameInfo>,
+    empty_frame_mode: EmptyFrameMode,
+    resample_filter: ResampleFilter,
+    png_options: PngOptions,
+    atlas_output: AtlasOutput,
+    zip_layout: ZipLayout,
+    zip_image_folder: Option<String>,
+    write_manifest: bool,
+    animation_color_ops: HashMap<String, Vec<ColorOp>>,
+    animation_effects: HashMap<String, Vec<ImageEffect>>,
+    background_removal: Option<BackgroundRemoval>,
+    quantization_reports: Vec<QuantizationReport>,
+    import_errors: Vec<String>,
+    progress: ProgressReporter,
+    export_job: Option<ExportJob>,
     _spritesheet_store: HashMap<String, image::DynamicImage>,
     _frame_count: usize
 }
@@ -105,14 +607,152 @@ impl GrowingPacker
             img_padding: padding,
             frame_image_cache: ImageCache::new(),
             frames: HashMap::new(),
+            empty_frames: vec![],
+            empty_frame_mode: EmptyFrameMode::Pixel,
+            resample_filter: ResampleFilter::Nearest,
+            png_options: PngOptions::default(),
+            atlas_output: AtlasOutput::Png,
+            zip_layout: ZipLayout::Flat,
+            zip_image_folder: None,
+            write_manifest: false,
+            animation_color_ops: HashMap::new(),
+            animation_effects: HashMap::new(),
+            background_removal: None,
+            quantization_reports: vec![],
+            import_errors: vec![],
+            progress: ProgressReporter::default(),
+            export_job: None,
             _spritesheet_store: HashMap::new(),
             _frame_count: 0
         }
     }
 
-    pub fn add_image_to_store(&mut self, img_key: String, img_data: Vec<u8>)
+    pub fn set_empty_frame_mode(&mut self, mode: EmptyFrameMode)
+    {
+        self.empty_frame_mode = mode;
+    }
+
+    /// Sets the filter used to resize frames that don't specify their own
+    pub fn set_resample_filter(&mut self, filter: ResampleFilter)
+    {
+        self.resample_filter = filter;
+    }
+
+    /// Makes `make_packed_image` write the spritesheet with premultiplied alpha
+    pub fn set_premultiplied_output(&mut self, premultiply_alpha: bool)
+    {
+        self.png_options.premultiply_alpha = premultiply_alpha;
+    }
+
+    /// Makes `make_packed_image` write indexed PNGs with at most `max_colors` (up to 256) colors. Pass `None` to go back to full color
+    pub fn set_indexed_output(&mut self, max_colors: Option<u16>, dither: bool)
+    {
+        self.png_options.quantization = max_colors.map(|max_colors| Quantization { max_colors, dither });
+    }
+
+    /// Sets the zlib compression level and row filter used for the spritesheet PNGs
+    pub fn set_png_encoding(&mut self, compression: PngCompression, filter: PngFilter)
+    {
+        self.png_options.compression = compression;
+        self.png_options.filter = filter;
+    }
+
+    /// Makes `make_packed_image` run a lossless optimization pass over the spritesheet PNGs. 
+    /// This is slow, but the output is never bigger than without it
+    pub fn set_png_optimization(&mut self, optimize: bool)
+    {
+        self.png_options.optimize = optimize;
+    }
+
+    /// Makes `make_packed_image` write block compressed DDS or KTX2 textures instead of PNGs. Pass `None` to go back to PNG.
+    /// Premultiplied output still applies, but indexed output and the PNG settings don't
+    pub fn set_gpu_texture_output(&mut self, container: Option<TextureContainer>, compression: BlockCompression, mipmaps: bool)
+    {
+        self.atlas_output = match container {
+            Some(container) => AtlasOutput::GpuTexture(GpuTextureOptions { container, compression, mipmaps }),
+            None => AtlasOutput::Png
+        };
+    }
+
+    /// Makes `make_packed_image` reduce the spritesheets to 16 bits per pixel, and write them as a PNG preview (to check for banding), 
+    /// raw pixels or an uncompressed DDS. Pass `None` to go back to full color PNGs. Replaces any GPU texture output
+    pub fn set_sixteen_bit_output(&mut self, format: Option<SixteenBitFormat>, dithering: Dithering, container: SixteenBitContainer)
+    {
+        self.atlas_output = match format {
+            Some(format) => AtlasOutput::SixteenBit(SixteenBitOptions { format, dithering, container }),
+            None => AtlasOutput::Png
+        };
+    }
+
+    /// Sets where files go inside the zip from `make_packed_image`, so that it can be extracted straight into a mod folder.
+    /// `image_folder` overrides the layout's folder for the spritesheets (e.g. `shared/images/characters`)
+    pub fn set_zip_layout(&mut self, layout: ZipLayout, image_folder: Option<String>)
+    {
+        self.zip_layout = layout;
+        self.zip_image_folder = image_folder.map(|folder| modbundle::normalize_folder(&folder));
+    }
+
+    /// Makes `make_packed_image` also write a `<name>.manifest.json` next to the spritesheets, 
+    /// listing the generator settings, the frames of each animation and a hash of every file in the zip
+    pub fn set_bundle_manifest(&mut self, write_manifest: bool)
+    {
+        self.write_manifest = write_manifest;
+    }
+
+    /// Sets a function that exports call as `callback(phase, done, total)` after every frame or spritesheet they finish.
+    /// `phase` is one of `"scaling"`, `"packing"`, `"drawing"` or `"encoding"` (repeated for every scale) when packing, and `"writing"` for image sequences and animation previews
+    pub fn set_progress_callback(&mut self, callback: Option<js_sys::Function>)
+    {
+        self.progress.set_callback(callback);
+    }
+
+    /// Exports check the token between frames, and stop with an error once it's cancelled. 
+    /// The packer shares the token with JS, so cancelling the same object later still works
+    pub fn set_cancellation_token(&mut self, token: &CancellationToken)
+    {
+        self.progress.set_token(Some(token.clone()));
+    }
+
+    /// The quantization error of each spritesheet (in the order of the scales) from the last call to `make_packed_image`. 
+    /// Only available when indexed output is on
+    pub fn quantization_report(&self, atlas_index: usize) -> Option<QuantizationReport>
+    {
+        self.quantization_reports.get(atlas_index).copied()
+    }
+
+    /// Sets the color operations applied to every frame of an animation (before any per-frame ones)
+    pub fn set_animation_color_ops(&mut self, animation_prefix: String, color_ops: &ColorOps)
+    {
+        self.animation_color_ops.insert(animation_prefix, color_ops.ops.clone());
+    }
+
+    /// Sets the effects applied to every frame of an animation (before any per-frame ones)
+    pub fn set_animation_effects(&mut self, animation_prefix: String, effects: &FrameEffects)
+    {
+        self.animation_effects.insert(animation_prefix, effects.effects.clone());
+    }
+
+    /// Makes every pixel within `tolerance` (0 by default) of `color` (given as `0xRRGGBB`) transparent in images imported after this, 
+    /// for old rips that have a solid background instead of alpha. Pass no color to turn background removal off
+    pub fn set_color_key(&mut self, color: Option<u32>, tolerance: Option<u8>)
+    {
+        self.background_removal = color.map(|color| BackgroundRemoval::ColorKey { color: rgb_from_u32(color), tolerance: tolerance.unwrap_or(0) });
+    }
+
+    /// Like `set_color_key`, but only removes the background that touches the corners of each image (flood filling out from them), 
+    /// so the background color can still be used inside the sprite. Replaces any color key
+    pub fn set_background_flood_fill(&mut self, enabled: bool, tolerance: Option<u8>)
+    {
+        self.background_removal = enabled.then(|| BackgroundRemoval::FloodFill { tolerance: tolerance.unwrap_or(0) });
+    }
+
+    /// Spritesheets get their background removed as a whole, so sprite detection and grid slicing see it as transparent too.
+    /// Returns an error (without storing anything) if the image can't be loaded
+    pub fn add_image_to_store(&mut self, img_key: String, img_data: Vec<u8>) -> Result<(), JsError>
     {
-        self._spritesheet_store.insert(img_key, image::load_from_memory(&img_data).expect("Expected valid image. Got invalid image!"));
+        let img = self.remove_background(image::load_from_memory(&img_data)?);
+        self._spritesheet_store.insert(img_key, img);
+        Ok(())
     }
 
     pub fn add_single_frame(
@@ -128,12 +768,18 @@ impl GrowingPacker
         frame_y: i64,
         frame_width: u64,
         frame_height: u64,
-        clip_to_bbox: bool
-    )
+        clip_to_bbox: bool,
+        options: &FrameOptions
+    ) -> Result<(), JsError>
     {
+        let filter = options.resample_filter.unwrap_or(self.resample_filter);
+        let rotation = options.rotation;
+        let color_ops = self.frame_color_ops(&animation_prefix, &options.color_ops);
+        let effects = self.frame_effects(&animation_prefix, &options.effects);
+        let img = self.remove_background(image::load_from_memory(&img_data)?);
         self._add_frame(
-            image::load_from_memory(&img_data).expect("Should be valid image"), 
-            TransformInfo { new_width, new_height, flip_x, flip_y }, 
+            img, 
+            TransformInfo { new_width, new_height, flip_x, flip_y, rotation, filter, color_ops, effects }, 
             animation_prefix, 
             FrameRectInfo { 
                 frame_x, 
@@ -142,7 +788,8 @@ impl GrowingPacker
                 frame_height
             },
             clip_to_bbox
-        );
+        )?;
+        Ok(())
     }
 
     pub fn add_spritesheet_frame(
@@ -162,13 +809,15 @@ impl GrowingPacker
         frame_y: i64,
         frame_width: u64,
         frame_height: u64,
-        clip_to_bbox: bool
-    )
+        clip_to_bbox: bool,
+        options: &FrameOptions
+    ) -> Result<(), JsError>
     {
-        let pre_img = self._spritesheet_store
-            .get(&spritesheet_id)
-            .expect("Key not in map!")
-            .crop_imm(rect_x, rect_y, rect_width, rect_height);
+        let filter = options.resample_filter.unwrap_or(self.resample_filter);
+        let rotation = options.rotation;
+        let color_ops = self.frame_color_ops(&animation_prefix, &options.color_ops);
+        let effects = self.frame_effects(&animation_prefix, &options.effects);
+        let pre_img = self.stored_spritesheet(&spritesheet_id)?.crop_imm(rect_x, rect_y, rect_width, rect_height);
 
         self._add_frame(
             pre_img, 
@@ -176,7 +825,11 @@ impl GrowingPacker
                 new_width, 
                 new_height, 
                 flip_x, 
-                flip_y 
+                flip_y,
+                rotation,
+                filter,
+                color_ops,
+                effects
             }, 
             animation_prefix, 
             FrameRectInfo { 
@@ -186,7 +839,231 @@ impl GrowingPacker
                 frame_height 
             },
             clip_to_bbox
-        );
+        )?;
+        Ok(())
+    }
+
+    /// Adds every frame of an animated GIF, APNG or WebP to the animation (or the one frame of a still image), composited the same way a browser plays them.
+    /// Frames are trimmed like any other frame, and get the animation's color operations and effects.
+    /// 
+    /// With `dedupe_held_frames`, frames that look exactly the same as the frame before them are skipped, since animations often repeat a frame to hold it.
+    /// The time a skipped frame was shown for is added to the frame before it.
+    /// 
+    /// Flixel plays every frame for the same time, so with `target_fps` each frame is added as many times as it takes to last as long as it did in the image
+    /// (frames shorter than half a tick are dropped). The repeats are the same image, so they don't take any extra space on the spritesheet.
+    /// 
+    /// Returns how many milliseconds each added frame is shown for, in the order they were added
+    pub fn add_animated_image(&mut self, img_data: Vec<u8>, animation_prefix: String, dedupe_held_frames: Option<bool>, target_fps: Option<f32>) -> Result<Vec<f64>, JsError>
+    {
+        if let Some(fps) = target_fps
+        {
+            if !(fps.is_finite() && fps > 0.0)
+            {
+                return Err(JsError::new(&format!("The target fps must be positive, got {}", fps)));
+            }
+        }
+        let dedupe_held_frames = dedupe_held_frames.unwrap_or(false);
+
+        let mut frames: Vec<(image::RgbaImage, f64)> = vec![];
+        for (frame, delay_ms) in utils::decode_animation_frames(&img_data)?
+        {
+            let frame = self.remove_background(DynamicImage::ImageRgba8(frame)).into_rgba8();
+            match frames.last_mut() {
+                Some((previous_frame, previous_delay_ms)) if dedupe_held_frames && *previous_frame == frame => *previous_delay_ms += delay_ms,
+                _ => frames.push((frame, delay_ms))
+            }
+        }
+        if let Some(fps) = target_fps
+        {
+            frames = repeat_frames_for_fps(frames, fps);
+        }
+
+        let mut durations = Vec::with_capacity(frames.len());
+        for (frame, delay_ms) in frames
+        {
+            self.add_whole_image_frame(DynamicImage::ImageRgba8(frame), &animation_prefix, true)?;
+            durations.push(delay_ms);
+        }
+        Ok(durations)
+    }
+
+    /// Slices a spritesheet added with `add_image_to_store` into a grid of equally sized cells, and adds every cell (row by row) to the animation.
+    /// Fully transparent cells are skipped. The cells are trimmed like any other frame, so they stay lined up in their frames.
+    /// Returns the number of frames added, or an error if the grid's cells are empty or not a single one fits in the spritesheet
+    pub fn add_grid_frames(&mut self, spritesheet_id: String, animation_prefix: String, grid: &GridLayout) -> Result<usize, JsError>
+    {
+        let cells: Vec<DynamicImage> = {
+            let spritesheet = self.stored_spritesheet(&spritesheet_id)?;
+            grid.validate(spritesheet.width(), spritesheet.height()).map_err(|err| JsError::new(&err))?;
+            grid.cells(spritesheet.width(), spritesheet.height())
+                .into_iter()
+                .map(|(x, y)| spritesheet.crop_imm(x, y, grid.cell_width, grid.cell_height))
+                .filter(|cell| utils::get_bounding_box(cell, None).is_some())
+                .collect()
+        };
+
+        let added = cells.len();
+        for cell in cells
+        {
+            self.add_whole_image_frame(cell, &animation_prefix, true)?;
+        }
+        Ok(added)
+    }
+
+    /// Finds the separate sprites on a spritesheet added with `add_image_to_store` (see `add_detected_frames`), and returns their bounding boxes 
+    /// as `[x, y, width, height, x, y, width, height, ...]`, e.g. to show them for review before adding them with `add_spritesheet_frame`
+    pub fn detect_sprites(&self, spritesheet_id: String, alpha_threshold: Option<u8>, merge_distance: Option<u32>) -> Result<Vec<u32>, JsError>
+    {
+        let spritesheet = self.stored_spritesheet(&spritesheet_id)?;
+        Ok(spritedetection::detect_sprites(&spritesheet.to_rgba8(), alpha_threshold.unwrap_or(spritedetection::DEFAULT_ALPHA_THRESHOLD), merge_distance.unwrap_or(0))
+            .into_iter()
+            .flat_map(|rect| [rect.x, rect.y, rect.width, rect.height])
+            .collect())
+    }
+
+    /// Finds the separate sprites on a spritesheet added with `add_image_to_store`, and adds each one (in reading order) to the animation. 
+    /// A sprite is a group of connected pixels that has at least one pixel with an alpha above `alpha_threshold` (32 by default), along with any other groups 
+    /// within `merge_distance` pixels of it (0 by default, i.e. only touching ones).
+    /// 
+    /// Every sprite gets the same frame, as big as the biggest sprite, and sits at the bottom center of it, so that the animation doesn't jump around
+    /// between sprites of different sizes. Returns the number of frames added
+    pub fn add_detected_frames(&mut self, spritesheet_id: String, animation_prefix: String, alpha_threshold: Option<u8>, merge_distance: Option<u32>) -> Result<usize, JsError>
+    {
+        let sprites: Vec<(DynamicImage, SpriteRect)> = {
+            let spritesheet = self.stored_spritesheet(&spritesheet_id)?;
+            spritedetection::detect_sprites(&spritesheet.to_rgba8(), alpha_threshold.unwrap_or(spritedetection::DEFAULT_ALPHA_THRESHOLD), merge_distance.unwrap_or(0))
+                .into_iter()
+                .map(|rect| (spritesheet.crop_imm(rect.x, rect.y, rect.width, rect.height), rect))
+                .collect()
+        };
+        let frame_width = sprites.iter().map(|(_, rect)| rect.width).max().unwrap_or(0);
+        let frame_height = sprites.iter().map(|(_, rect)| rect.height).max().unwrap_or(0);
+
+        let added = sprites.len();
+        for (sprite, rect) in sprites
+        {
+            let frame_rect = FrameRectInfo {
+                frame_x: -(((frame_width - rect.width) / 2) as i64),
+                frame_y: -((frame_height - rect.height) as i64),
+                frame_width: frame_width as u64,
+                frame_height: frame_height as u64
+            };
+            self.add_image_frame(sprite, &animation_prefix, false, false, frame_rect, true)?;
+        }
+        Ok(added)
+    }
+
+    /// Adds everything in a zip (a whole mod folder, say) at once. Every PNG with an XML of the same name next to it is added as a spritesheet,
+    /// with all of its frames, and every other PNG is added as a single frame. Spritesheets and loose frames are added in natural order of their paths,
+    /// so `idle2.png` comes before `idle10.png`. The spritesheets are kept in the store as `zip:<path in the zip>`, so they can't replace ones added by hand.
+    ///
+    /// Animation prefixes are the frame names with their numeric suffix removed. Files that can't be read are skipped, and listed by `import_errors`.
+    /// Returns the number of frames added, or an error if the zip itself can't be read
+    pub fn add_zip(&mut self, zip_data: Vec<u8>, options: &ZipImportOptions) -> Result<usize, JsError>
+    {
+        let contents = zipimport::read_zip(&zip_data, options)?;
+        self.import_errors = contents.errors;
+        let mut added = 0;
+        for zip_atlas in contents.atlases
+        {
+            let spritesheet = match image::load_from_memory(&zip_atlas.image_data) {
+                Ok(img) => self.remove_background(img),
+                Err(err) => {
+                    self.import_errors.push(format!("{}: {}", zip_atlas.path, err));
+                    continue;
+                }
+            };
+            for subtexture in zip_atlas.atlas.subtextures
+            {
+                // cropped here rather than with `add_spritesheet_frame`, so that rotated subtextures can be turned upright first
+                let (frame_x, frame_y, frame_width, frame_height) = subtexture.frame_rect();
+                self.add_image_frame(
+                    subtexture.upright_image(&spritesheet),
+                    zipimport::strip_numeric_suffix(&subtexture.name, options.max_suffix_digits),
+                    subtexture.flip_x.unwrap_or(false),
+                    subtexture.flip_y.unwrap_or(false),
+                    FrameRectInfo { frame_x: frame_x as i64, frame_y: frame_y as i64, frame_width: frame_width as u64, frame_height: frame_height as u64 },
+                    options.clip_to_bbox
+                )?;
+                added += 1;
+            }
+            self._spritesheet_store.insert(format!("zip:{}", zip_atlas.path), spritesheet);
+        }
+
+        for frame in contents.frames
+        {
+            let img = match image::load_from_memory(&frame.image_data) {
+                Ok(img) => self.remove_background(img),
+                Err(err) => {
+                    self.import_errors.push(format!("{}: {}", frame.path, err));
+                    continue;
+                }
+            };
+            self.add_whole_image_frame(img, &frame.animation_prefix, options.clip_to_bbox)?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// The files the last `add_zip` skipped because they couldn't be read, as `path: reason`
+    pub fn import_errors(&self) -> Vec<String>
+    {
+        self.import_errors.clone()
+    }
+
+    /// Adds an image as a frame that fills its whole frame rect, with the animation's color operations and effects
+    fn add_whole_image_frame(&mut self, img: DynamicImage, animation_prefix: &str, clip_to_bbox: bool) -> Result<(), TransformError>
+    {
+        let frame_rect = FrameRectInfo { frame_x: 0, frame_y: 0, frame_width: img.width() as u64, frame_height: img.height() as u64 };
+        self.add_image_frame(img, animation_prefix, false, false, frame_rect, clip_to_bbox)
+    }
+
+    /// Adds an image at its own size inside the given frame rect, with the animation's color operations and effects
+    fn add_image_frame(&mut self, img: DynamicImage, animation_prefix: &str, flip_x: bool, flip_y: bool, frame_rect: FrameRectInfo, clip_to_bbox: bool) -> Result<(), TransformError>
+    {
+        let transform = TransformInfo {
+            new_width: img.width(),
+            new_height: img.height(),
+            flip_x,
+            flip_y,
+            rotation: 0.0,
+            filter: self.resample_filter,
+            color_ops: self.frame_color_ops(animation_prefix, &[]),
+            effects: self.frame_effects(animation_prefix, &[])
+        };
+        self._add_frame(img, transform, animation_prefix.to_string(), frame_rect, clip_to_bbox)
+    }
+
+    fn stored_spritesheet(&self, spritesheet_id: &str) -> Result<&DynamicImage, JsError>
+    {
+        self._spritesheet_store
+            .get(spritesheet_id)
+            .ok_or_else(|| JsError::new(&format!("No spritesheet was added with the key \"{}\"", spritesheet_id)))
+    }
+
+    /// Applies the background removal set with `set_color_key` or `set_background_flood_fill`, if any
+    fn remove_background(&self, img: DynamicImage) -> DynamicImage
+    {
+        match self.background_removal {
+            Some(removal) => utils::remove_background(img, removal),
+            None => img
+        }
+    }
+
+    /// The animation's color operations followed by the frame's own
+    fn frame_color_ops(&self, animation_prefix: &str, frame_color_ops: &[ColorOp]) -> Vec<ColorOp>
+    {
+        let mut ops = self.animation_color_ops.get(animation_prefix).cloned().unwrap_or_default();
+        ops.extend_from_slice(frame_color_ops);
+        ops
+    }
+
+    /// The animation's effects followed by the frame's own
+    fn frame_effects(&self, animation_prefix: &str, frame_effects: &[ImageEffect]) -> Vec<ImageEffect>
+    {
+        let mut effects = self.animation_effects.get(animation_prefix).cloned().unwrap_or_default();
+        effects.extend_from_slice(frame_effects);
+        effects
     }
 
     fn _add_frame(
@@ -196,15 +1073,25 @@ impl GrowingPacker
         animation_prefix: String,
         raw_frame_rect: FrameRectInfo,
         clip_to_bbox: bool
-    )
+    ) -> Result<(), TransformError>
     {
-        let true_img = transform_image(frame_img, transform);
-        let (imghash, (left, top, _right, _bottom)) = self.frame_image_cache.add_image(
+        let raw_frame_rect = raw_frame_rect.transformed(frame_img.width(), frame_img.height(), &transform);
+        let true_img = transform_image(frame_img, transform)?;
+        let cached = self.frame_image_cache.add_image(
             true_img,
             self.img_padding,
             clip_to_bbox
         );
 
+        let cached = match (cached, self.empty_frame_mode) {
+            (Some(c), _) => Some(c),
+            (None, EmptyFrameMode::Pixel) => Some(self.frame_image_cache.add_empty_pixel()),
+            (None, EmptyFrameMode::ZeroSize) => None,
+            (None, EmptyFrameMode::Drop) => return Ok(())
+        };
+        // empty frames have no trimmed area to offset by
+        let (left, top) = cached.map(|(_, (left, top, _, _))| (left, top)).unwrap_or((0, 0));
+
         let cur_frameinfo = FrameInfo {
             // spr_id,
             // img_cache_id: imghash,
@@ -219,171 +1106,1211 @@ impl GrowingPacker
         };
         self._frame_count += 1;
         
-        let imgframes = self.frames.get_mut(&imghash);
-        match imgframes {
-            Some(frames) => {
-                frames.push(cur_frameinfo);
+        match cached {
+            Some((imghash, _)) => self.frames.entry(imghash).or_default().push(cur_frameinfo),
+            None => self.empty_frames.push(cur_frameinfo)
+        }
+        Ok(())
+    }
+
+    /// Returns a zip containing the spritesheet PNG and XML.
+    /// 
+    /// If `scales` is given, a separate PNG/XML pair is packed for every scale factor. Any scale other than `1` gets an `@<scale>x` suffix in its file names.
+    /// Repeated scales are only packed once. 
+    /// The other scales are resized from the frames as they were added (already transformed and trimmed), not from the original images, 
+    /// so a frame that was shrunk when it was added won't get its detail back at `@2x`
+    pub fn make_packed_image(&mut self, scales: Option<Vec<f32>>) -> Result<Vec<u8>, JsError>
+    {
+        Ok(self.make_export(scales)?.to_zip())
+    }
+
+    /// Same as `make_packed_image`, but returns each spritesheet image, XML and frame placement separately instead of zipping them up
+    pub fn make_export(&mut self, scales: Option<Vec<f32>>) -> Result<PackedExport, JsError>
+    {
+        self.start_export(scales)?;
+        Ok(self.run_export()?)
+    }
+
+    /// Sets up the same export as `make_export` without doing any of the work, so that it can be done bit by bit with `export_step`
+    /// (e.g. from a Web Worker that reports progress in between). Any export that was already in progress is dropped.
+    /// Fails if any scale isn't a positive number
+    pub fn start_export(&mut self, scales: Option<Vec<f32>>) -> Result<(), JsError>
+    {
+        let mut scales = scales.filter(|s| !s.is_empty()).unwrap_or_else(|| vec![1.0]);
+        if let Some(scale) = scales.iter().find(|scale| !(scale.is_finite() && **scale > 0.0))
+        {
+            return Err(JsError::new(&format!("Scale factors must be positive, got {}", scale)));
+        }
+        // the same scale twice would write two spritesheets with the same file name
+        let mut seen = vec![];
+        scales.retain(|scale| {
+            let is_new = !seen.contains(scale);
+            seen.push(*scale);
+            is_new
+        });
+        let image_folder = self.zip_image_folder.clone().unwrap_or_else(|| self.zip_layout.default_image_folder().to_string());
+        self.export_job = Some(ExportJob::Spritesheets { scales, image_folder, atlases: vec![], current: None });
+        Ok(())
+    }
+
+    /// Sets up the same export as `make_img_sequence`, to be done bit by bit with `export_step`. Any export that was already in progress is dropped
+    pub fn start_img_sequence(&mut self, unique_only: bool, per_animation_folders: Option<bool>, manifest: Option<SequenceManifest>)
+    {
+        let ordered = self.frames_in_order();
+        let paths = sequence_paths(&ordered, per_animation_folders.unwrap_or(false));
+
+        let mut export = PackedExport::new(String::new(), vec![]);
+        let mut items = vec![];
+        // the file each frame ends up in, and where the file's image sits inside the frame
+        let mut frame_files: Vec<(Option<&str>, i64, i64)> = Vec::with_capacity(ordered.len());
+        if unique_only
+        {
+            // every image is written once, named after the first frame that uses it
+            let mut unique_paths: HashMap<u64, &str> = HashMap::new();
+            for (&(imghash, f), (_, path)) in ordered.iter().zip(&paths)
+            {
+                let file = imghash.map(|imghash| *unique_paths.entry(imghash).or_insert_with(|| {
+                    items.push((path.clone(), SequenceItem::UniqueImage(imghash)));
+                    path
+                }));
+                frame_files.push((file, -f.frame_rect.frame_x, -f.frame_rect.frame_y));
             }
-            None => {
-                self.frames.insert(imghash, vec![ cur_frameinfo ]);
+        }
+        else
+        {
+            for (&(imghash, f), (_, path)) in ordered.iter().zip(&paths)
+            {
+                items.push((path.clone(), SequenceItem::Frame(imghash, f.frame_rect)));
+                frame_files.push((Some(path), 0, 0));
+            }
+        }
+
+        if let Some(format) = manifest
+        {
+            let rows: Vec<SequenceManifestRow> = ordered
+                .iter()
+                .zip(&paths)
+                .zip(frame_files)
+                .map(|((&(_, f), &(frame_number, _)), (file, offset_x, offset_y))| SequenceManifestRow {
+                    prefix: &f.animation_prefix,
+                    frame_number,
+                    file,
+                    offset_x,
+                    offset_y,
+                    frame_width: f.frame_rect.frame_width,
+                    frame_height: f.frame_rect.frame_height
+                })
+                .collect();
+            let (path, bytes) = match format {
+                SequenceManifest::Json => ("frames.json", sequence_manifest_json(&rows)),
+                SequenceManifest::Csv => ("frames.csv", sequence_manifest_csv(&rows))
+            };
+            export.add_file(path.to_string(), bytes);
+        }
+
+        self.export_job = Some(ExportJob::ImageSequence { items, written: 0, export });
+    }
+
+    /// Does up to `max_steps` pieces of work on the started export, reporting progress after each one. A piece is a single frame 
+    /// (resized, drawn or written out), or packing or encoding a single spritesheet.
+    /// 
+    /// Returns `true` once the export is done and can be taken with `finish_export`. If the cancellation token is cancelled or a spritesheet can't be packed, 
+    /// the export is dropped and an error is returned. Also returns an error if no export has been started
+    pub fn export_step(&mut self, max_steps: u32) -> Result<bool, JsError>
+    {
+        let job = self.export_job.take().ok_or_else(|| JsError::new("No export has been started"))?;
+        Ok(self.advance_export_steps(job, max_steps)?)
+    }
+
+    /// Takes the result of an export once `export_step` has returned `true`. Image sequences can be zipped up with `to_zip`.
+    /// Returns an error (and keeps the export going) if it isn't finished yet
+    pub fn finish_export(&mut self) -> Result<PackedExport, JsError>
+    {
+        match self.export_job.take() {
+            Some(ExportJob::Finished(export)) => Ok(export),
+            job => {
+                self.export_job = job;
+                Err(JsError::new("The export isn't finished yet"))
+            }
+        }
+    }
+
+    fn advance_export_steps(&mut self, mut job: ExportJob, max_steps: u32) -> Result<bool, ExportError>
+    {
+        for _ in 0..max_steps
+        {
+            if let ExportJob::Finished(_) = job
+            {
+                break;
+            }
+            self.progress.check_cancelled()?;
+            job = self.advance_export(job)?;
+        }
+        let finished = matches!(job, ExportJob::Finished(_));
+        self.export_job = Some(job);
+        Ok(finished)
+    }
+
+    /// Runs the export that was just started to the end
+    fn run_export(&mut self) -> Result<PackedExport, ExportError>
+    {
+        let job = self.export_job.take().expect("An export should have just been started");
+        self.advance_export_steps(job, u32::MAX)?;
+        match self.export_job.take() {
+            Some(ExportJob::Finished(export)) => Ok(export),
+            _ => unreachable!("The export was run to the end")
+        }
+    }
+
+    /// Does a single piece of work on the export
+    fn advance_export(&mut self, job: ExportJob) -> Result<ExportJob, PackError>
+    {
+        let job = match job {
+            ExportJob::Spritesheets { scales, image_folder, mut atlases, current } => {
+                let mut atlas_job = current.unwrap_or_else(|| self.start_atlas(scales[atlases.len()]));
+                match self.atlas_step(&mut atlas_job)? {
+                    Some(atlas) => {
+                        atlases.push(atlas);
+                        if atlases.len() == scales.len()
+                        {
+                            return Ok(ExportJob::Finished(self.finish_spritesheets(&scales, image_folder, atlases)));
+                        }
+                        ExportJob::Spritesheets { scales, image_folder, atlases, current: None }
+                    },
+                    None => ExportJob::Spritesheets { scales, image_folder, atlases, current: Some(atlas_job) }
+                }
+            },
+            ExportJob::ImageSequence { items, mut written, mut export } => {
+                if let Some((path, item)) = items.get(written)
+                {
+                    export.add_file(path.clone(), self.sequence_file(item));
+                    written += 1;
+                    self.progress.report("writing", written, items.len());
+                }
+                if written == items.len()
+                {
+                    return Ok(ExportJob::Finished(export));
+                }
+                ExportJob::ImageSequence { items, written, export }
+            },
+            finished => finished
+        };
+        Ok(job)
+    }
+
+    /// Adds the files that go along with the spritesheets (character file, manifest)
+    fn finish_spritesheets(&mut self, scales: &[f32], image_folder: String, atlases: Vec<PackedAtlas>) -> PackedExport
+    {
+        self.quantization_reports = atlases.iter().filter_map(|atlas| atlas.quantization_report).collect();
+        let mut export = PackedExport::new(image_folder.clone(), atlases);
+
+        let animations = self.animation_summaries();
+        let prefixes: Vec<&str> = animations.iter().map(|animation| animation.prefix.as_str()).collect();
+        if let Some((path, bytes)) = modbundle::character_file(self.zip_layout, &self.character_name, &image_folder, &prefixes)
+        {
+            export.add_file(path, bytes);
+        }
+        if self.write_manifest
+        {
+            let manifest = self.bundle_manifest(scales, &animations, &export.files());
+            export.add_file(format!("{}{}.manifest.json", image_folder, self.character_name), manifest);
+        }
+        export
+    }
+
+    /// Every frame (including empty ones) in the order they were added, along with the hash of its cached image
+    fn frames_in_order(&self) -> Vec<(Option<u64>, &FrameInfo)>
+    {
+        let mut ordered: Vec<(Option<u64>, &FrameInfo)> = self.frames
+            .iter()
+            .flat_map(|(hash, frames)| frames.iter().map(move |f| (Some(*hash), f)))
+            .chain(self.empty_frames.iter().map(|f| (None, f)))
+            .collect();
+        ordered.sort_by_key(|(_, f)| f._index);
+        ordered
+    }
+
+    /// The animations in the order they first appear
+    fn animation_summaries(&self) -> Vec<AnimationSummary>
+    {
+        let mut summaries: Vec<(AnimationSummary, crc32fast::Hasher)> = Vec::new();
+        for (hash, frame) in self.frames_in_order()
+        {
+            let index = match summaries.iter().position(|(summary, _)| summary.prefix == frame.animation_prefix) {
+                Some(index) => index,
+                None => {
+                    summaries.push((AnimationSummary { prefix: frame.animation_prefix.clone(), frame_count: 0, crc: 0 }, crc32fast::Hasher::new()));
+                    summaries.len() - 1
+                }
+            };
+            let (summary, hasher) = &mut summaries[index];
+            summary.frame_count += 1;
+            if let Some(img) = hash.and_then(|hash| self.frame_image_cache.cache.get(&hash))
+            {
+                hasher.update(img.as_bytes());
+            }
+            let rect = &frame.frame_rect;
+            for value in [rect.frame_x, rect.frame_y, rect.frame_width as i64, rect.frame_height as i64]
+            {
+                hasher.update(&value.to_le_bytes());
             }
         }
+
+        summaries
+            .into_iter()
+            .map(|(summary, hasher)| AnimationSummary { crc: hasher.finalize(), ..summary })
+            .collect()
     }
 
-    /// Returns the final image as a PNG
-    pub fn make_packed_image(&mut self) -> Vec<u8>
+    fn bundle_manifest(&self, scales: &[f32], animations: &[AnimationSummary], files: &[(String, &[u8])]) -> Vec<u8>
     {
-        let (final_width, final_height, fits) = self.pack().expect("Packing error happened!!");
-        let mut base = image::DynamicImage::new_rgba8(final_width, final_height);
+        let output = match &self.atlas_output {
+            AtlasOutput::Png => json!({
+                "format": "png",
+                "compression": format!("{:?}", self.png_options.compression),
+                "filter": format!("{:?}", self.png_options.filter),
+                "optimize": self.png_options.optimize,
+                "indexed": self.png_options.quantization.map(|q| json!({ "maxColors": q.max_colors, "dither": q.dither }))
+            }),
+            AtlasOutput::GpuTexture(options) => json!({
+                "format": format!("{:?}", options.container),
+                "compression": format!("{:?}", options.compression),
+                "mipmaps": options.mipmaps
+            }),
+            AtlasOutput::SixteenBit(options) => json!({
+                "format": format!("{:?}", options.container),
+                "pixelFormat": format!("{:?}", options.format),
+                "dithering": format!("{:?}", options.dithering)
+            })
+        };
+
+        let manifest = json!({
+            "generator": format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            "character": self.character_name,
+            "settings": {
+                "padding": self.img_padding,
+                "scales": scales,
+                "resampleFilter": format!("{:?}", self.resample_filter),
+                "emptyFrameMode": format!("{:?}", self.empty_frame_mode),
+                "premultipliedAlpha": self.png_options.premultiply_alpha,
+                "output": output,
+                "zipLayout": format!("{:?}", self.zip_layout)
+            },
+            "frames": {
+                "total": self._frame_count,
+                "unique": self.frame_image_cache.cache.len(),
+                "empty": self.empty_frames.len()
+            },
+            "animations": animations.iter().map(|animation| json!({
+                "prefix": animation.prefix,
+                "frames": animation.frame_count,
+                "crc32": format!("{:08x}", animation.crc)
+            })).collect::<Vec<_>>(),
+            "files": files.iter().map(|(path, bytes)| json!({
+                "path": path,
+                "size": bytes.len(),
+                "crc32": format!("{:08x}", crc32fast::hash(bytes))
+            })).collect::<Vec<_>>()
+        });
+        serde_json::to_vec_pretty(&manifest).expect("Could not write manifest json!")
+    }
+
+    /// `scale` has already been checked by `start_export`
+    fn start_atlas(&self, scale: f32) -> AtlasJob
+    {
+        AtlasJob {
+            name: if scale == 1.0 { self.character_name.clone() } else { format!("{}@{}x", self.character_name, scale) },
+            scale,
+            scaled_images: HashMap::new(),
+            to_scale: if scale == 1.0 { vec![] } else { self.frame_image_cache.cache.keys().copied().collect() },
+            packed: None,
+            drawn: 0
+        }
+    }
+
+    /// Does a single piece of work on the spritesheet: resizing or drawing one image, packing, or encoding. Returns the spritesheet once it's encoded
+    fn atlas_step(&self, job: &mut AtlasJob) -> Result<Option<PackedAtlas>, PackError>
+    {
+        if let Some(imghash) = job.to_scale.pop()
+        {
+            job.scaled_images.insert(imghash, self.scale_frame_image(imghash, job.scale));
+            self.progress.report("scaling", job.scaled_images.len(), self.frame_image_cache.cache.len());
+            return Ok(None);
+        }
+
+        let frame_images = if job.scale == 1.0 { &self.frame_image_cache.cache } else { &job.scaled_images };
+        let atlas = match &mut job.packed {
+            None => {
+                self.progress.report("packing", 0, 1);
+                let (final_width, final_height, fits) = pack_images(frame_images)?;
+                // the atlas can end up empty if every frame was written as a zero-size SubTexture
+                job.packed = Some((image::DynamicImage::new_rgba8(final_width.max(1), final_height.max(1)), fits));
+                self.progress.report("packing", 1, 1);
+                None
+            },
+            Some((base, fits)) if job.drawn < fits.len() => {
+                let fit = &fits[job.drawn];
+                imageops::overlay(base, &frame_images[&fit.id], fit.x as i64, fit.y as i64);
+                job.drawn += 1;
+                self.progress.report("drawing", job.drawn, fits.len());
+                None
+            },
+            Some(_) => {
+                self.progress.report("encoding", 0, 1);
+                let atlas = self.encode_atlas(job);
+                self.progress.report("encoding", 1, 1);
+                Some(atlas)
+            }
+        };
+        Ok(atlas)
+    }
+
+    /// Writes the XML of a fully drawn spritesheet, and encodes its image (PNG, or a GPU texture if enabled)
+    fn encode_atlas(&self, job: &AtlasJob) -> PackedAtlas
+    {
+        let (base, fits) = job.packed.as_ref().expect("The spritesheet should be packed before it's encoded");
+        let (atlas_name, scale) = (job.name.clone(), job.scale);
+        let unique_images = if scale == 1.0 { self.frame_image_cache.cache.len() } else { job.scaled_images.len() };
 
         let mut xml_bytes = Vec::new();
         let mut texture_atlas = textureatlas_format::TextureAtlas::default();
-        texture_atlas.image_path = self.character_name.clone() + ".png";
+        texture_atlas.image_path = atlas_name.clone() + self.atlas_image_extension();
         texture_atlas.subtextures = vec![SubTexture::default(); self._frame_count];
         
+        let mut stats = PackStats {
+            width: base.width(),
+            height: base.height(),
+            frame_count: self._frame_count as u32,
+            unique_images: unique_images as u32,
+            empty_frames: self.empty_frames.len() as u32,
+            occupancy: 0.0
+        };
+        let mut packed_area = 0u64;
+
         // group frames by id
         for fit in fits
         {
-            imageops::overlay(&mut base, &self.frame_image_cache.cache[&fit.id], fit.x as i64, fit.y as i64);
+            packed_area += fit.width as u64 * fit.height as u64;
             let frame_group = self.frames.get(&fit.id);
             if let Some(frames) = frame_group {
+                // the empty pixel is never padded, so it has no padding to keep unscaled
+                let padding = if Some(fit.id) == self.frame_image_cache._empty_img_hash { 0 } else { self.img_padding };
                 for f in frames
                 {
+                    let frame_rect = f.frame_rect.scaled(scale, padding);
                     texture_atlas.subtextures[f._index] = SubTexture::new(
                         f.animation_prefix.clone(), 
                         fit.x, 
                         fit.y, 
                         fit.width, 
                         fit.height, 
-                        Some(f.frame_rect.frame_x as i32), 
-                        Some(f.frame_rect.frame_y as i32), 
-                        Some(f.frame_rect.frame_width as u32), 
-                        Some(f.frame_rect.frame_height as u32),
+                        Some(frame_rect.frame_x as i32), 
+                        Some(frame_rect.frame_y as i32), 
+                        Some(frame_rect.frame_width as u32), 
+                        Some(frame_rect.frame_height as u32),
                         None, // TODO: Test if adding flipX and flipY to xml works in flixel
                         None
                     );
                 }
             }
         }
+        for f in &self.empty_frames
+        {
+            let frame_rect = f.frame_rect.scaled(scale, 0);
+            texture_atlas.subtextures[f._index] = SubTexture::new(
+                f.animation_prefix.clone(), 
+                0, 0, 0, 0, 
+                Some(frame_rect.frame_x as i32), 
+                Some(frame_rect.frame_y as i32), 
+                Some(frame_rect.frame_width as u32), 
+                Some(frame_rect.frame_height as u32),
+                None,
+                None
+            );
+        }
         texture_atlas.write_to(&mut xml_bytes);
+        stats.occupancy = (packed_area as f64 / (stats.width as u64 * stats.height as u64) as f64) as f32;
+        let placements = texture_atlas.subtexture_names()
+            .into_iter()
+            .zip(&texture_atlas.subtextures)
+            .map(|(name, subtexture)| FramePlacement::from_subtexture(name, subtexture))
+            .collect();
         
-        let pngbytes = encode_image_as_png(&base);
-        
-        let mut zip_buf: Vec<u8> = Vec::with_capacity(pngbytes.len());
-        let zipcursor = io::Cursor::new(&mut zip_buf);
-        
-        let mut zip_writer = zip::ZipWriter::new(zipcursor);
-        let zip_opts = zip::write::FileOptions::default();
-        
-        zip_writer.start_file(self.character_name.clone() + ".png", zip_opts).expect("Could not write to zip!");
-        zip_writer.write_all(&pngbytes).expect("Zip error!");
+        // textures are written exactly as they will be uploaded, so they're premultiplied here if needed
+        let texture_pixels = || {
+            let mut rgba_img = base.to_rgba8();
+            if self.png_options.premultiply_alpha
+            {
+                utils::premultiply_alpha(&mut rgba_img);
+            }
+            rgba_img
+        };
+        let (image_bytes, quantization_report) = match &self.atlas_output {
+            AtlasOutput::Png => encode_image_as_png_with_report(base, &self.png_options),
+            AtlasOutput::GpuTexture(options) => {
+                (texturewriter::encode_gpu_texture(&texture_pixels(), options, self.png_options.premultiply_alpha), None)
+            },
+            AtlasOutput::SixteenBit(options) => {
+                // the preview is a normal PNG meant to be looked at, so it's reduced from the straight alpha pixels
+                let rgba_img = if options.container == SixteenBitContainer::PngPreview { base.to_rgba8() } else { texture_pixels() };
+                let (width, height) = rgba_img.dimensions();
+                let reduced = bitdepth::reduce(&rgba_img, options.format, options.dithering);
+                let image_bytes = match options.container {
+                    SixteenBitContainer::PngPreview => {
+                        let preview = image::DynamicImage::ImageRgba8(bitdepth::preview(width, height, &reduced, options.format));
+                        encode_image_as_png(&preview, &PngOptions { premultiply_alpha: false, quantization: None, ..self.png_options })
+                    },
+                    SixteenBitContainer::Raw => bitdepth::pack(&reduced, options.format),
+                    SixteenBitContainer::Dds => texturewriter::write_16bit_dds(width, height, &bitdepth::pack(&reduced, options.format), options.format.masks())
+                };
+                (image_bytes, None)
+            }
+        };
 
-        zip_writer.start_file(self.character_name.clone() + ".xml", zip_opts).expect("Could not write to zip!");
-        zip_writer.write_all(&xml_bytes).expect("Zip error!");
+        PackedAtlas {
+            name: atlas_name,
+            scale,
+            image_extension: self.atlas_image_extension(),
+            image_bytes,
+            xml: String::from_utf8(xml_bytes).expect("The XML should be valid UTF-8"),
+            placements,
+            stats,
+            quantization_report
+        }
+    }
 
-        zip_writer.finish().expect("Error finising zip!");
-        drop(zip_writer);
+    fn atlas_image_extension(&self) -> &'static str
+    {
+        match &self.atlas_output {
+            AtlasOutput::Png => ".png",
+            AtlasOutput::GpuTexture(options) => options.extension(),
+            AtlasOutput::SixteenBit(options) => options.extension()
+        }
+    }
+
+    /// Resizes a cached frame image by `scale`. Padding is kept at its original size
+    fn scale_frame_image(&self, imghash: u64, scale: f32) -> DynamicImage
+    {
+        let padding = self.img_padding;
+        let img = &self.frame_image_cache.cache[&imghash];
+        if Some(imghash) == self.frame_image_cache._empty_img_hash
+        {
+            return img.clone();
+        }
+        let content = img.crop_imm(padding, padding, img.width() - 2*padding, img.height() - 2*padding);
+        let scaled = utils::resize_image(
+            &content,
+            scale_dimension(content.width(), scale),
+            scale_dimension(content.height(), scale),
+            self.resample_filter.into()
+        );
+        pad_image_uniform(scaled, padding)
+    }
 
-        return zip_buf;
+    /// Returns a zip with every frame as its own PNG, exactly as it would appear in flixel, named `<prefix><frame number>.png` in the order the frames were added.
+    /// Prefixes are made safe to use as file names first.
+    /// 
+    /// With `unique_only`, every distinct (trimmed) image is written just once instead, named after the first frame that uses it. 
+    /// With `per_animation_folders`, each animation's frames go in a folder named after its prefix.
+    /// With `manifest`, a file is added at the root of the zip listing every frame, the file it uses and where that file's image sits inside the frame.
+    /// Returns an error if the cancellation token is cancelled
+    pub fn make_img_sequence(&mut self, unique_only: bool, per_animation_folders: Option<bool>, manifest: Option<SequenceManifest>) -> Result<Vec<u8>, JsError>
+    {
+        self.start_img_sequence(unique_only, per_animation_folders, manifest);
+        Ok(self.run_export()?.to_zip())
     }
 
-    pub fn make_img_sequence(&self, unique_only: bool) -> Vec<u8>
+    /// The contents of a single file of an image sequence
+    fn sequence_file(&self, item: &SequenceItem) -> Vec<u8>
     {
-        let mut zip_buf: Vec<u8> = Vec::new();
-        let zipcursor = io::Cursor::new(&mut zip_buf);
-        
-        let mut zip_writer = zip::ZipWriter::new(zipcursor);
-        let zip_opts = zip::write::FileOptions::default();
+        match item {
+            SequenceItem::Frame(imghash, frame_rect) => encode_image_as_png(&self.recreate_frame(*imghash, frame_rect), &PngOptions::default()),
+            SequenceItem::UniqueImage(imghash) => encode_image_as_png(&self.frame_image_cache.cache[imghash], &PngOptions::default()),
+            SequenceItem::AnimationPreview(frames, options) => {
+                let frame_images: Vec<DynamicImage> = frames.iter().map(|(imghash, frame_rect)| self.recreate_frame(*imghash, frame_rect)).collect();
+                let composed = animpreview::compose_frames(&frame_images, options, self.resample_filter.into());
+                animpreview::encode_preview(&composed, options, self.png_options.compression)
+            }
+        }
+    }
 
-        let mut prefix_counter = PrefixCounter::new();
+    fn recreate_frame(&self, imghash: Option<u64>, frame_rect: &FrameRectInfo) -> DynamicImage
+    {
+        recreate_frame(imghash.and_then(|imghash| self.frame_image_cache.cache.get(&imghash)), frame_rect)
+    }
 
-        if !unique_only
+    /// Renders a frame of an animation (counting from 0, in the order the frames were added) exactly as it will be in the XML packed at `scale`,
+    /// i.e. the frame's image placed inside its frame rect the way flixel does it. Returns nothing if there's no such frame, and an error if the scale isn't positive
+    pub fn render_frame(&self, animation_prefix: String, index: usize, scale: f32) -> Result<Option<RenderedFrame>, JsError>
+    {
+        if !(scale.is_finite() && scale > 0.0)
         {
-            for (imghash, frames) in self.frames.iter()
-            {
-                let img = self.frame_image_cache.cache.get(imghash);
-                if let Some(im) = img {
-                    for f in frames
-                    {
-                        // re-create the exact frame as it would appear in flixel
-
-                        let mut final_dims = (f.frame_rect.frame_width, f.frame_rect.frame_height);
-                        let mut placing_position = (0, 0);
-                        if f.frame_rect.frame_x > 0
-                        {
-                            final_dims.0 += f.frame_rect.frame_x as u64;
-                        }
-                        else
-                        {
-                            placing_position.0 = -f.frame_rect.frame_x;
-                        }
+            return Err(JsError::new(&format!("Scale factors must be positive, got {}", scale)));
+        }
+        let frame = self.frames_in_order()
+            .into_iter()
+            .filter(|(_, f)| f.animation_prefix == animation_prefix)
+            .nth(index);
+        let Some((imghash, f)) = frame else { return Ok(None) };
 
-                        if f.frame_rect.frame_y > 0
-                        {
-                            final_dims.1 += f.frame_rect.frame_y as u64;
-                        }
-                        else
-                        {
-                            placing_position.1 = -f.frame_rect.frame_y;
-                        }
+        // the same image and frame rect the packed XML would use
+        let img = match imghash {
+            Some(imghash) if scale != 1.0 => Some(std::borrow::Cow::Owned(self.scale_frame_image(imghash, scale))),
+            Some(imghash) => Some(std::borrow::Cow::Borrowed(&self.frame_image_cache.cache[&imghash])),
+            None => None
+        };
+        let padding = if imghash.is_none() || imghash == self.frame_image_cache._empty_img_hash { 0 } else { self.img_padding };
+        let frame_rect = f.frame_rect.scaled(scale, padding);
+        Ok(Some(recreate_frame(img.as_deref(), &frame_rect).into_rgba8().into()))
+    }
 
-                        let mut final_frame = DynamicImage::new_rgba8(final_dims.0 as u32, final_dims.1 as u32);
-                        imageops::overlay(&mut final_frame, im, placing_position.0, placing_position.1);
-                        
-                        let pngbytes = encode_image_as_png(&final_frame);
-                        let anim_num = prefix_counter.add_prefix(&f.animation_prefix);
-                        zip_writer.start_file(format!("{}{}.png", f.animation_prefix, anim_num), zip_opts).expect("Error writing to zip!");
-                        zip_writer.write_all(&pngbytes).expect("Zipping error!");
-                    }
-                }
-            }
+    /// Returns an animated GIF or APNG of a single animation, with every frame rebuilt the same way `make_img_sequence` does it.
+    /// Returns nothing if there's no animation with that prefix, and an error if the fps or scale in the options can't be used
+    pub fn make_animation_preview(&self, animation_prefix: String, options: &AnimationPreviewOptions) -> Result<Option<Vec<u8>>, JsError>
+    {
+        options.validate()?;
+        let frames: FrameList = self.frames_in_order()
+            .into_iter()
+            .filter(|(_, f)| f.animation_prefix == animation_prefix)
+            .map(|(imghash, f)| (imghash, f.frame_rect))
+            .collect();
+        if frames.is_empty()
+        {
+            return Ok(None);
         }
-        else
+        Ok(Some(self.sequence_file(&SequenceItem::AnimationPreview(frames, *options))))
+    }
+
+    /// Returns a zip with a preview of every animation (see `make_animation_preview`), named after its prefix
+    pub fn make_animation_previews(&mut self, options: &AnimationPreviewOptions) -> Result<Vec<u8>, JsError>
+    {
+        self.start_animation_previews(options)?;
+        Ok(self.run_export()?.to_zip())
+    }
+
+    /// Sets up the same export as `make_animation_previews`, to be done bit by bit with `export_step`. Any export that was already in progress is dropped.
+    /// Returns an error (without dropping anything) if the fps or scale in the options can't be used
+    pub fn start_animation_previews(&mut self, options: &AnimationPreviewOptions) -> Result<(), JsError>
+    {
+        options.validate()?;
+        let mut animations: Vec<(&str, FrameList)> = vec![];
+        for (imghash, f) in self.frames_in_order()
         {
-            for (imghash, img) in self.frame_image_cache.cache.iter()
-            {
-                let pngbytes = encode_image_as_png(img);
-                zip_writer.start_file(format!("image_frame-{imghash}.png"), zip_opts).expect("Could not start file");
-                zip_writer.write_all(&pngbytes).expect("Could not write png file");
+            match animations.iter_mut().find(|(prefix, _)| *prefix == f.animation_prefix) {
+                Some((_, frames)) => frames.push((imghash, f.frame_rect)),
+                None => animations.push((&f.animation_prefix, vec![(imghash, f.frame_rect)]))
             }
         }
-        zip_writer.finish().expect("Error finishing zip!");
-        drop(zip_writer);
 
-        zip_buf
+        // prefixes that sanitize to the same name get a number added, so they don't overwrite each other
+        let mut name_counter = PrefixCounter::new();
+        let items = animations
+            .into_iter()
+            .map(|(prefix, frames)| {
+                let name = match utils::sanitize_file_name(prefix) {
+                    name if name.is_empty() => "animation".to_string(),
+                    name => name
+                };
+                let path = match name_counter.add_prefix(&name) {
+                    0 => format!("{}{}", name, options.extension()),
+                    n => format!("{}_{}{}", name, n, options.extension())
+                };
+                (path, SequenceItem::AnimationPreview(frames, *options))
+            })
+            .collect();
+        self.export_job = Some(ExportJob::ImageSequence { items, written: 0, export: PackedExport::new(String::new(), vec![]) });
+        Ok(())
     }
 }
 
-impl Packer for GrowingPacker
+/// Repeats every frame for the number of ticks at `fps` that it's shown during, counting from the start of the animation so that rounding doesn't add up.
+/// Animations without any delays (e.g. still images) are left as they are
+fn repeat_frames_for_fps(frames: Vec<(image::RgbaImage, f64)>, fps: f32) -> Vec<(image::RgbaImage, f64)>
 {
-    fn pack(&mut self) -> Result<(u32, u32, Vec<FitRect>), PackError>
+    let tick_ms = 1000.0 / fps as f64;
+    if frames.iter().all(|(_, delay_ms)| *delay_ms <= 0.0)
+    {
+        return frames;
+    }
+
+    let mut repeated = vec![];
+    let mut start_ms = 0.0;
+    for (frame, delay_ms) in frames
     {
-        let mut rects = vec![];
-        for (imghash, img) in self.frame_image_cache.cache.iter()
+        let end_ms = start_ms + delay_ms;
+        let ticks = (end_ms / tick_ms).round() as usize - (start_ms / tick_ms).round() as usize;
+        repeated.extend(std::iter::repeat_n((frame, tick_ms), ticks));
+        start_ms = end_ms;
+    }
+    repeated
+}
+
+/// The number of every frame within its animation (as in the XML), along with the path of its file in an image sequence.
+/// Frames are numbered by their sanitized prefix in file names, so that prefixes that sanitize to the same name can't overwrite each other
+fn sequence_paths(ordered: &[(Option<u64>, &FrameInfo)], per_animation_folders: bool) -> Vec<(u32, String)>
+{
+    let names: Vec<String> = ordered.iter().map(|(_, f)| utils::sanitize_file_name(&f.animation_prefix)).collect();
+    let mut frame_counts: HashMap<&str, u32> = HashMap::new();
+    for name in &names
+    {
+        *frame_counts.entry(name).or_default() += 1;
+    }
+
+    let mut prefix_counter = PrefixCounter::new();
+    let mut name_counter = PrefixCounter::new();
+    ordered
+        .iter()
+        .zip(&names)
+        .map(|((_, f), name)| {
+            let frame_number = prefix_counter.add_prefix(&f.animation_prefix);
+            let file_number = name_counter.add_prefix(name);
+            // same as the XML, unless the animation is too long for 4 digits
+            let digits = (frame_counts[name.as_str()] - 1).to_string().len().max(4);
+            let folder = if per_animation_folders && !name.is_empty() { format!("{}/", name) } else { String::new() };
+            (frame_number, format!("{}{}{:0digits$}.png", folder, name, file_number, digits = digits))
+        })
+        .collect()
+}
+
+/// A single frame, as listed in an image sequence's manifest
+struct SequenceManifestRow<'a>
+{
+    prefix: &'a str,
+    frame_number: u32,
+    /// `None` for empty frames in unique-only sequences, which have no image
+    file: Option<&'a str>,
+    offset_x: i64,
+    offset_y: i64,
+    frame_width: u64,
+    frame_height: u64
+}
+
+impl SequenceManifestRow<'_>
+{
+    /// The frame's `SubTexture` name
+    fn name(&self) -> String
+    {
+        format!("{}{:04}", self.prefix, self.frame_number)
+    }
+}
+
+fn sequence_manifest_json(rows: &[SequenceManifestRow]) -> Vec<u8>
+{
+    let manifest = json!({
+        "frames": rows.iter().map(|row| json!({
+            "name": row.name(),
+            "prefix": row.prefix,
+            "frame": row.frame_number,
+            "file": row.file,
+            "offsetX": row.offset_x,
+            "offsetY": row.offset_y,
+            "frameWidth": row.frame_width,
+            "frameHeight": row.frame_height
+        })).collect::<Vec<_>>()
+    });
+    serde_json::to_vec_pretty(&manifest).expect("Could not write manifest json!")
+}
+
+fn sequence_manifest_csv(rows: &[SequenceManifestRow]) -> Vec<u8>
+{
+    // quotes a field if it has anything in it that would break the row apart
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r'])
         {
-            rects.push(PackingRectangle{
-                width: img.width(),
-                height: img.height(),
-                id: *imghash
-            });
+            format!("\"{}\"", value.replace('"', "\"\""))
         }
-        let result = helpers::bin_pack(rects.into_iter(), |im| (im.width*im.height) as i32)?;
-        
-        Ok(
-            (
-                result.width(), 
-                result.height(), 
-                result.items
-                .into_iter()
-                .map(
-                    |(i, elem)| FitRect::new(
-                        elem.x as u32, elem.y as u32, 
-                        elem.width as u32, elem.height as u32, 
-                        i
-                    )
+        else
+        {
+            value.to_string()
+        }
+    };
+
+    let mut csv = String::from("name,prefix,frame,file,offsetX,offsetY,frameWidth,frameHeight\n");
+    for row in rows
+    {
+        csv += &format!(
+            "{},{},{},{},{},{},{},{}\n",
+            field(&row.name()),
+            field(row.prefix),
+            row.frame_number,
+            field(row.file.unwrap_or("")),
+            row.offset_x,
+            row.offset_y,
+            row.frame_width,
+            row.frame_height
+        );
+    }
+    csv.into_bytes()
+}
+
+/// Re-creates the exact frame as it would appear in flixel, the same way `render_frame` does. A `None` image produces an empty frame
+fn recreate_frame(img: Option<&DynamicImage>, frame_rect: &FrameRectInfo) -> DynamicImage
+{
+    DynamicImage::ImageRgba8(framerender::render(img, frame_rect.frame_x, frame_rect.frame_y, frame_rect.frame_width as u32, frame_rect.frame_height as u32))
+}
+
+/// Packs the given images, using their hashes as the ids of the resulting `FitRect`s
+fn pack_images(images: &HashMap<u64, DynamicImage>) -> Result<(u32, u32, Vec<FitRect>), PackError>
+{
+    let mut rects = vec![];
+    for (imghash, img) in images.iter()
+    {
+        rects.push(PackingRectangle{
+            width: img.width(),
+            height: img.height(),
+            id: *imghash
+        });
+    }
+    let result = helpers::bin_pack(rects.into_iter(), |im| (im.width*im.height) as i32)?;
+    
+    Ok(
+        (
+            result.width(), 
+            result.height(), 
+            result.items
+            .into_iter()
+            .map(
+                |(i, elem)| FitRect::new(
+                    elem.x as u32, elem.y as u32, 
+                    elem.width as u32, elem.height as u32, 
+                    i
                 )
-                .collect()
             )
+            .collect()
         )
+    )
+}
+
+impl Packer for GrowingPacker
+{
+    fn pack(&mut self) -> Result<(u32, u32, Vec<FitRect>), PackError>
+    {
+        pack_images(&self.frame_image_cache.cache)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::{Cursor, Read};
+
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8>
+    {
+        let img = RgbaImage::from_fn(width, height, |x, y| Rgba(pixel(x, y)));
+        encode_image_as_png(&DynamicImage::ImageRgba8(img), &PngOptions::default())
+    }
+
+    /// A red square in the middle of a `size`x`size` image
+    fn square(size: u32, square_size: u32) -> Vec<u8>
+    {
+        let start = (size - square_size) / 2;
+        png(size, size, |x, y| if (start..start + square_size).contains(&x) && (start..start + square_size).contains(&y) { [255, 0, 0, 255] } else { [0; 4] })
+    }
+
+    fn unzip(data: &[u8]) -> Vec<(String, Vec<u8>)>
+    {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).expect("Not a zip");
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).expect("Could not read the file");
+                let mut contents = vec![];
+                file.read_to_end(&mut contents).expect("Could not read the file");
+                (file.name().to_string(), contents)
+            })
+            .collect()
+    }
+
+    /// Every frame of `make_img_sequence`, in order
+    fn sequence_frames(packer: &mut GrowingPacker, unique_only: bool) -> Vec<RgbaImage>
+    {
+        let zip = packer.make_img_sequence(unique_only, None, None).expect("Nothing cancels the export");
+        unzip(&zip)
+            .into_iter()
+            .map(|(_, data)| image::load_from_memory(&data).expect("Not a PNG").to_rgba8())
+            .collect()
+    }
+
+    fn rendered(packer: &GrowingPacker, animation_prefix: &str, index: usize) -> RgbaImage
+    {
+        let frame = packer.render_frame(animation_prefix.to_string(), index, 1.0).expect("The scale is valid").expect("No such frame");
+        RgbaImage::from_raw(frame.width, frame.height, frame.pixels()).expect("Wrong number of pixels")
+    }
+
+    fn transform(new_width: u32, new_height: u32, flip_x: bool, flip_y: bool, rotation: f32) -> TransformInfo
+    {
+        TransformInfo { new_width, new_height, flip_x, flip_y, rotation, filter: ResampleFilter::Nearest, color_ops: vec![], effects: vec![] }
+    }
+
+    fn rect(frame_x: i64, frame_y: i64, frame_width: u64, frame_height: u64) -> FrameRectInfo
+    {
+        FrameRectInfo { frame_x, frame_y, frame_width, frame_height }
+    }
+
+    #[test]
+    fn quarter_turns_turn_the_frame()
+    {
+        // a sprite that fills its frame still fills it
+        assert_eq!(rect(0, 0, 100, 50).transformed(100, 50, &transform(100, 50, false, false, 90.0)), rect(0, 0, 50, 100));
+        assert_eq!(rect(0, 0, 100, 50).transformed(100, 50, &transform(100, 50, false, false, -90.0)), rect(0, 0, 50, 100));
+        assert_eq!(rect(0, 0, 100, 50).transformed(100, 50, &transform(100, 50, false, false, 180.0)), rect(0, 0, 100, 50));
+
+        // a 4x2 image at (3, 1) in a 10x6 frame ends up at (3, 3) in a 6x10 frame after a clockwise turn
+        assert_eq!(rect(-3, -1, 10, 6).transformed(4, 2, &transform(4, 2, false, false, 90.0)), rect(-3, -3, 6, 10));
+        // and at (1, 3) after a counterclockwise one
+        assert_eq!(rect(-3, -1, 10, 6).transformed(4, 2, &transform(4, 2, false, false, 270.0)), rect(-1, -3, 6, 10));
+    }
+
+    #[test]
+    fn other_angles_keep_the_frame()
+    {
+        // the image is centered on the frame, and so is its 5x5 canvas after turning it
+        assert_eq!(utils::rotated_dimensions(4, 2, 45.0), (5, 5));
+        assert_eq!(rect(-3, -2, 10, 6).transformed(4, 2, &transform(4, 2, false, false, 45.0)), rect(-3, -1, 10, 6));
+    }
+
+    #[test]
+    fn flips_mirror_a_trimmed_frame()
+    {
+        // a 4x2 image at (1, 1) in a 10x6 frame
+        let trimmed = rect(-1, -1, 10, 6);
+        assert_eq!(trimmed.transformed(4, 2, &transform(4, 2, true, false, 0.0)), rect(-5, -1, 10, 6));
+        assert_eq!(trimmed.transformed(4, 2, &transform(4, 2, false, true, 0.0)), rect(-1, -3, 10, 6));
+        assert_eq!(trimmed.transformed(4, 2, &transform(4, 2, true, true, 0.0)), rect(-5, -3, 10, 6));
+    }
+
+    #[test]
+    fn resizing_scales_the_frame()
+    {
+        assert_eq!(rect(-1, -1, 10, 6).transformed(4, 2, &transform(8, 4, false, false, 0.0)), rect(-2, -2, 20, 12));
+        assert_eq!(rect(-3, -1, 10, 6).transformed(4, 2, &transform(2, 2, false, false, 0.0)), rect(-2, -1, 5, 6));
+    }
+
+    #[test]
+    fn effects_grow_the_frame()
+    {
+        let mut outlined = transform(4, 2, false, false, 0.0);
+        outlined.effects.push(ImageEffect::Outline { thickness: 2, color: [0, 0, 0, 255] });
+        assert_eq!(rect(-1, -1, 10, 6).transformed(4, 2, &outlined), rect(-1, -1, 14, 10));
+    }
+
+    #[test]
+    fn scaling_keeps_the_padding()
+    {
+        // the padded image sits at (3, 1), so the image itself is at (5, 3)
+        assert_eq!(rect(-3, -1, 10, 6).scaled(2.0, 2), rect(-8, -4, 20, 12));
+        assert_eq!(rect(-3, -1, 10, 6).scaled(0.5, 2), rect(-1, 0, 5, 3));
+        assert_eq!(rect(-3, -1, 10, 6).scaled(1.0, 2), rect(-3, -1, 10, 6));
+    }
+
+    #[test]
+    fn rotated_frames_are_not_cut_off()
+    {
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        let mut options = FrameOptions::new();
+        options.rotation = 90.0;
+        packer.add_single_frame(png(4, 2, |_, _| [255, 0, 0, 255]), "idle".to_string(), 4, 2, false, false, 0, 0, 4, 2, false, &options).unwrap();
+        let frame = rendered(&packer, "idle", 0);
+        assert_eq!(frame.dimensions(), (2, 4));
+        assert!(frame.pixels().all(|px| px.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn palette_swaps_map_colors_by_index()
+    {
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        let mut color_ops = ColorOps::new();
+        color_ops.palette_swap(vec![0xff0000, 0x00ff00], vec![0x0000ff, 0xffffff]).unwrap();
+        packer.set_animation_color_ops("idle".to_string(), &color_ops);
+        packer.add_single_frame(png(2, 1, |x, _| if x == 0 { [255, 0, 0, 255] } else { [0, 255, 0, 128] }), "idle".to_string(), 2, 1, false, false, 0, 0, 2, 1, false, &FrameOptions::new()).unwrap();
+        let frame = rendered(&packer, "idle", 0);
+        assert_eq!(frame.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(frame.get_pixel(1, 0).0, [255, 255, 255, 128]);
+    }
+
+    #[test]
+    fn cancelled_exports_are_dropped()
+    {
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        packer.add_single_frame(square(4, 2), "idle".to_string(), 4, 4, false, false, 0, 0, 4, 4, true, &FrameOptions::new()).unwrap();
+        let token = CancellationToken::new();
+        packer.set_cancellation_token(&token);
+
+        token.cancel();
+        packer.start_img_sequence(false, None, None);
+        assert!(matches!(packer.run_export(), Err(ExportError::Cancelled(_))));
+        assert!(packer.export_job.is_none());
+
+        token.reset();
+        assert_eq!(sequence_frames(&mut packer, false).len(), 1);
+    }
+
+    #[test]
+    fn grid_cells_go_row_by_row()
+    {
+        // the 1 pixel left over on the right doesn't fit a whole cell
+        assert_eq!(GridLayout::new(2, 2).cells(7, 4), vec![(0, 0), (2, 0), (4, 0), (0, 2), (2, 2), (4, 2)]);
+    }
+
+    #[test]
+    fn grid_cells_skip_margins_and_spacing()
+    {
+        let mut grid = GridLayout::new(3, 2);
+        (grid.margin_x, grid.margin_y, grid.spacing_x, grid.spacing_y) = (1, 1, 1, 1);
+        assert_eq!(grid.cells(11, 7), vec![(1, 1), (5, 1), (1, 4), (5, 4)]);
+        // the last cell needs the margin after it, but not the spacing
+        assert_eq!(grid.cells(9, 7), vec![(1, 1), (5, 1), (1, 4), (5, 4)]);
+        assert_eq!(grid.cells(8, 7), vec![(1, 1), (1, 4)]);
+    }
+
+    #[test]
+    fn grid_cells_can_be_limited_to_a_range()
+    {
+        let mut grid = GridLayout::new(1, 1);
+        (grid.first_row, grid.first_column, grid.column_count) = (1, 2, Some(2));
+        assert_eq!(grid.cells(5, 3), vec![(2, 1), (3, 1), (2, 2), (3, 2)]);
+
+        // counts are cut off at the edge of the image, and ranges past it are empty
+        grid.row_count = Some(10);
+        assert_eq!(grid.cells(5, 3).len(), 4);
+        grid.first_row = 3;
+        assert!(grid.cells(5, 3).is_empty());
+    }
+
+    #[test]
+    fn grids_without_a_fitting_cell_are_rejected()
+    {
+        assert!(GridLayout::new(2, 2).validate(4, 4).is_ok());
+        assert!(GridLayout::new(0, 2).validate(4, 4).is_err());
+        assert!(GridLayout::new(2, 0).validate(4, 4).is_err());
+        assert!(GridLayout::new(5, 2).validate(4, 4).is_err());
+        assert!(GridLayout::new(2, 5).validate(4, 4).is_err());
+
+        let mut grid = GridLayout::new(2, 2);
+        grid.margin_x = 2;
+        assert!(grid.validate(5, 4).is_err());
+        assert!(grid.validate(6, 4).is_ok());
+    }
+
+    #[test]
+    fn huge_grids_dont_overflow()
+    {
+        let mut grid = GridLayout::new(1, 1);
+        (grid.spacing_x, grid.spacing_y) = (u32::MAX, u32::MAX);
+        assert_eq!(grid.cells(1, 1), vec![(0, 0)]);
+        assert!(GridLayout::new(u32::MAX, u32::MAX).cells(4, 4).is_empty());
+    }
+
+    #[test]
+    fn grid_frames_skip_empty_cells()
+    {
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        packer.add_image_to_store("sheet".to_string(), png(4, 4, |x, y| if x < 2 && y >= 2 { [0; 4] } else { [255, 0, 0, 255] })).unwrap();
+        assert_eq!(packer.add_grid_frames("sheet".to_string(), "idle".to_string(), &GridLayout::new(2, 2)).unwrap(), 3);
+        assert_eq!(rendered(&packer, "idle", 2).dimensions(), (2, 2));
+    }
+
+    /// An animated GIF with a solid color frame for every (color, delay in ms)
+    fn gif(frames: &[([u8; 4], u32)]) -> Vec<u8>
+    {
+        let mut data = vec![];
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut data);
+            for &(color, delay_ms) in frames
+            {
+                let frame = image::Frame::from_parts(RgbaImage::from_pixel(2, 2, Rgba(color)), 0, 0, image::Delay::from_numer_denom_ms(delay_ms, 1));
+                encoder.encode_frame(frame).expect("Could not encode the frame");
+            }
+        }
+        data
+    }
+
+    fn delays(frames: &[(RgbaImage, f64)]) -> Vec<f64>
+    {
+        frames.iter().map(|(_, delay_ms)| *delay_ms).collect()
+    }
+
+    /// Frames with the given delays, each a different 1x1 image so they can be told apart
+    fn timed_frames(delays_ms: &[f64]) -> Vec<(RgbaImage, f64)>
+    {
+        delays_ms.iter().enumerate().map(|(i, &delay_ms)| (RgbaImage::from_pixel(1, 1, Rgba([i as u8, 0, 0, 255])), delay_ms)).collect()
+    }
+
+    fn frame_numbers(frames: &[(RgbaImage, f64)]) -> Vec<u8>
+    {
+        frames.iter().map(|(frame, _)| frame.get_pixel(0, 0).0[0]).collect()
+    }
+
+    #[test]
+    fn animated_images_keep_their_delays()
+    {
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 255];
+        let data = gif(&[(RED, 30), (RED, 30), (BLUE, 100), (RED, 0)]);
+
+        // a delay of 0 plays at 100ms in browsers
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        assert_eq!(packer.add_animated_image(data.clone(), "idle".to_string(), None, None).unwrap(), vec![30.0, 30.0, 100.0, 100.0]);
+
+        // a held frame adds its time to the one before it
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        assert_eq!(packer.add_animated_image(data.clone(), "idle".to_string(), Some(true), None).unwrap(), vec![60.0, 100.0, 100.0]);
+
+        // at 20fps the frames last 1, 2 and 2 ticks
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        assert_eq!(packer.add_animated_image(data, "idle".to_string(), Some(true), Some(20.0)).unwrap(), vec![50.0; 5]);
+        let frames: Vec<[u8; 4]> = (0..5).map(|i| rendered(&packer, "idle", i).get_pixel(0, 0).0).collect();
+        assert_eq!(frames, vec![RED, BLUE, BLUE, RED, RED]);
+    }
+
+    #[test]
+    fn repeated_frames_round_from_the_start_of_the_animation()
+    {
+        // 50ms frames at 24fps are 1.2 ticks each, so the rounding error can't add up to a whole extra frame
+        let repeated = repeat_frames_for_fps(timed_frames(&[50.0, 50.0, 50.0]), 24.0);
+        assert_eq!(frame_numbers(&repeated), vec![0, 1, 2, 2]);
+        assert!(delays(&repeated).iter().all(|&delay_ms| (delay_ms - 1000.0 / 24.0).abs() < 1e-9));
+
+        // exact multiples are repeated exactly
+        let repeated = repeat_frames_for_fps(timed_frames(&[100.0, 300.0]), 10.0);
+        assert_eq!(frame_numbers(&repeated), vec![0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn lower_fps_drops_short_frames()
+    {
+        // 40fps played at 20fps keeps every other frame
+        let repeated = repeat_frames_for_fps(timed_frames(&[25.0; 4]), 20.0);
+        assert_eq!(frame_numbers(&repeated), vec![0, 2]);
+        assert_eq!(delays(&repeated), vec![50.0, 50.0]);
+    }
+
+    #[test]
+    fn frames_without_delays_are_not_repeated()
+    {
+        let repeated = repeat_frames_for_fps(timed_frames(&[0.0, 0.0]), 24.0);
+        assert_eq!(frame_numbers(&repeated), vec![0, 1]);
+        assert_eq!(delays(&repeated), vec![0.0, 0.0]);
+    }
+
+    fn render_frame_count(packer: &GrowingPacker) -> usize
+    {
+        (0..).take_while(|&i| packer.render_frame("idle".to_string(), i, 1.0).unwrap().is_some()).count()
+    }
+
+    /// A packer with a 2x2 square trimmed out of a 6x6 frame, an empty 6x6 frame, and the square again
+    fn packer_with_empty_frame(mode: EmptyFrameMode) -> GrowingPacker
+    {
+        let mut packer = GrowingPacker::new("bf".to_string(), 0);
+        packer.set_empty_frame_mode(mode);
+        for data in [square(6, 2), png(6, 6, |_, _| [0; 4]), square(6, 2)]
+        {
+            packer.add_single_frame(data, "idle".to_string(), 6, 6, false, false, 0, 0, 6, 6, true, &FrameOptions::new()).unwrap();
+        }
+        packer
+    }
+
+    /// The frames of the packed XML, as (name, x, y, width, height, frame_x, frame_y, frame_width, frame_height), ordered by name
+    fn packed_frames(packer: &mut GrowingPacker) -> Vec<(String, u32, u32, u32, u32, i32, i32, u32, u32)>
+    {
+        let export = packer.make_export(None).unwrap();
+        let mut subtextures = textureatlas_format::TextureAtlas::from_xml_string(&export.atlas(0).expect("No spritesheet").xml)
+            .expect("Invalid XML")
+            .subtextures;
+        subtextures.sort();
+        subtextures
+            .into_iter()
+            .map(|s| (s.name, s.x, s.y, s.width, s.height, s.frame_x.unwrap_or(0), s.frame_y.unwrap_or(0), s.frame_width.unwrap_or(0), s.frame_height.unwrap_or(0)))
+            .collect()
+    }
+
+    #[test]
+    fn pixel_mode_packs_a_transparent_pixel()
+    {
+        let mut packer = packer_with_empty_frame(EmptyFrameMode::Pixel);
+        let frames = packed_frames(&mut packer);
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].3, frames[0].4, frames[0].5, frames[0].6, frames[0].7, frames[0].8), (2, 2, -2, -2, 6, 6));
+        assert_eq!((frames[1].3, frames[1].4, frames[1].5, frames[1].6, frames[1].7, frames[1].8), (1, 1, 0, 0, 6, 6));
+        // both squares use the same image
+        assert_eq!((frames[2].1, frames[2].2), (frames[0].1, frames[0].2));
+
+        let sequence = sequence_frames(&mut packer, false);
+        assert_eq!(sequence.len(), 3);
+        assert_eq!(sequence[1], RgbaImage::new(6, 6));
+        // the pixel is an image like any other
+        assert_eq!(sequence_frames(&mut packer, true).len(), 2);
+    }
+
+    #[test]
+    fn zero_size_mode_writes_an_empty_subtexture()
+    {
+        let mut packer = packer_with_empty_frame(EmptyFrameMode::ZeroSize);
+        let frames = packed_frames(&mut packer);
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].3, frames[0].4, frames[0].5, frames[0].6, frames[0].7, frames[0].8), (2, 2, -2, -2, 6, 6));
+        assert_eq!(frames[1], ("idle0001".to_string(), 0, 0, 0, 0, 0, 0, 6, 6));
+
+        let sequence = sequence_frames(&mut packer, false);
+        assert_eq!(sequence.len(), 3);
+        assert_eq!(sequence[1], RgbaImage::new(6, 6));
+        assert_eq!(render_frame_count(&packer), 3);
+    }
+
+    #[test]
+    fn zero_size_frames_have_no_file_in_unique_sequences()
+    {
+        let mut packer = packer_with_empty_frame(EmptyFrameMode::ZeroSize);
+        let zip = packer.make_img_sequence(true, None, Some(SequenceManifest::Json)).unwrap();
+        let mut files = unzip(&zip);
+        files.sort();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["frames.json", "idle0000.png"]);
+
+        let manifest: serde_json::Value = serde_json::from_slice(&files[0].1).expect("Invalid JSON");
+        let frames = manifest["frames"].as_array().expect("No frames");
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0]["file"], "idle0000.png");
+        assert_eq!((frames[0]["offsetX"].as_i64(), frames[0]["frameWidth"].as_u64()), (Some(2), Some(6)));
+        assert!(frames[1]["file"].is_null());
+        assert_eq!((frames[1]["offsetX"].as_i64(), frames[1]["frameWidth"].as_u64(), frames[1]["frameHeight"].as_u64()), (Some(0), Some(6), Some(6)));
+        assert_eq!(frames[2]["file"], "idle0000.png");
+    }
+
+    #[test]
+    fn drop_mode_removes_the_frame()
+    {
+        let mut packer = packer_with_empty_frame(EmptyFrameMode::Drop);
+        let frames = packed_frames(&mut packer);
+        let names: Vec<&str> = frames.iter().map(|frame| frame.0.as_str()).collect();
+        assert_eq!(names, vec!["idle0000", "idle0001"]);
+        assert_eq!((frames[1].3, frames[1].4, frames[1].5, frames[1].6, frames[1].7, frames[1].8), (2, 2, -2, -2, 6, 6));
+        assert_eq!(sequence_frames(&mut packer, false).len(), 2);
+        assert_eq!(render_frame_count(&packer), 2);
+    }
+
+    #[test]
+    fn effected_frames_render_the_same_as_the_sequence()
+    {
+        let mut packer = GrowingPacker::new("bf".to_string(), 2);
+        let mut effects = FrameEffects::new();
+        effects.outline(1, 0x000000ff).unwrap();
+        effects.drop_shadow(2, 1, 0.0, 0x00000080).unwrap();
+        packer.set_animation_effects("idle".to_string(), &effects);
+        packer.add_single_frame(square(6, 2), "idle".to_string(), 6, 6, false, false, 0, 0, 6, 6, true, &FrameOptions::new()).unwrap();
+
+        let preview = rendered(&packer, "idle", 0);
+        let sequence = sequence_frames(&mut packer, false);
+        assert_eq!(sequence, vec![preview.clone()]);
+
+        // the frame grows by the outline on every side, and by the shadow towards the bottom right
+        assert_eq!(preview.dimensions(), (6 + 1 + 1 + 2, 6 + 1 + 1 + 1));
+        // the outline is drawn on both sides of the square
+        assert_eq!(preview.get_pixel(2, 3).0, [0, 0, 0, 255]);
+        assert_eq!(preview.get_pixel(5, 3).0, [0, 0, 0, 255]);
+        assert_eq!(preview.get_pixel(3, 3).0, [255, 0, 0, 255]);
     }
 }
\ No newline at end of file
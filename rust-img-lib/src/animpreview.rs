@@ -0,0 +1,123 @@
+//! Animated GIF and APNG previews of single animations, for showcase posts and quick reviews
+
+use std::time::Duration;
+
+use image::{codecs::gif::{GifEncoder, Repeat}, imageops, Delay, DynamicImage, Frame, RgbaImage};
+use wasm_bindgen::prelude::*;
+
+use crate::{pngwriter::{self, PngCompression}, utils};
+
+/// The file formats animation previews can be written in
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewFormat
+{
+    /// Pixels are either fully opaque or fully transparent, and frame delays are rounded to hundredths of a second
+    Gif,
+    /// Full color and alpha. Written with a `.png` extension, which is what most programs expect
+    Apng
+}
+
+/// Settings for `make_animation_preview`. Every field can be changed from JS after creating it with `new`
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationPreviewOptions
+{
+    pub format: PreviewFormat,
+    /// Frames per second
+    pub fps: f32,
+    /// Number of times the animation plays. `0` loops forever
+    pub loop_count: u32,
+    /// Color behind the frames, given as `0xRRGGBBAA`. Transparent by default
+    pub background: u32,
+    /// Resizes the frames (with the packer's resample filter)
+    pub scale: f32
+}
+
+#[wasm_bindgen]
+impl AnimationPreviewOptions
+{
+    /// 24 fps, looping forever over a transparent background, at the original size
+    pub fn new(format: PreviewFormat) -> Self
+    {
+        Self { format, fps: 24.0, loop_count: 0, background: 0, scale: 1.0 }
+    }
+}
+
+impl AnimationPreviewOptions
+{
+    /// Returns an error if the fps or scale can't be used
+    pub fn validate(&self) -> Result<(), JsError>
+    {
+        if !(self.fps.is_finite() && self.fps > 0.0)
+        {
+            return Err(JsError::new(&format!("The preview fps must be positive, got {}", self.fps)));
+        }
+        if !(self.scale.is_finite() && self.scale > 0.0)
+        {
+            return Err(JsError::new(&format!("The preview scale must be positive, got {}", self.scale)));
+        }
+        Ok(())
+    }
+
+    pub fn extension(&self) -> &'static str
+    {
+        match self.format {
+            PreviewFormat::Gif => ".gif",
+            PreviewFormat::Apng => ".png"
+        }
+    }
+}
+
+/// Draws the frames over the background on a canvas big enough for all of them, and scales them.
+/// Frames are lined up by their top left corners, the same way flixel lines them up when playing the animation. The options should have been validated
+pub fn compose_frames(frames: &[DynamicImage], options: &AnimationPreviewOptions, filter: imageops::FilterType) -> Vec<RgbaImage>
+{
+    let width = frames.iter().map(|frame| frame.width()).max().unwrap_or(1).max(1);
+    let height = frames.iter().map(|frame| frame.height()).max().unwrap_or(1).max(1);
+    let scaled_width = ((width as f64 * options.scale as f64).round() as u32).max(1);
+    let scaled_height = ((height as f64 * options.scale as f64).round() as u32).max(1);
+
+    frames
+        .iter()
+        .map(|frame| {
+            let mut canvas = RgbaImage::from_pixel(width, height, image::Rgba(options.background.to_be_bytes()));
+            imageops::overlay(&mut canvas, frame, 0, 0);
+            if (scaled_width, scaled_height) == (width, height)
+            {
+                return canvas;
+            }
+            utils::resize_image(&DynamicImage::ImageRgba8(canvas), scaled_width, scaled_height, filter).to_rgba8()
+        })
+        .collect()
+}
+
+/// Encodes frames made by `compose_frames` as an animation. `compression` is only used by APNGs. The options should have been validated
+pub fn encode_preview(frames: &[RgbaImage], options: &AnimationPreviewOptions, compression: PngCompression) -> Vec<u8>
+{
+    match options.format {
+        PreviewFormat::Gif => {
+            let mut out_vec = Vec::new();
+            {
+                let mut encoder = GifEncoder::new(&mut out_vec);
+                // gifs count the times the animation repeats after the first play, and play once without a count
+                match options.loop_count {
+                    0 => encoder.set_repeat(Repeat::Infinite).expect("Could not set gif repeat!"),
+                    1 => (),
+                    n => encoder.set_repeat(Repeat::Finite((n - 1).min(u16::MAX as u32) as u16)).expect("Could not set gif repeat!")
+                }
+                let delay = Delay::from_saturating_duration(Duration::from_secs_f64(1.0 / options.fps as f64));
+                for frame in frames
+                {
+                    encoder.encode_frame(Frame::from_parts(frame.clone(), 0, 0, delay)).expect("Error writing gif frame!");
+                }
+            }
+            out_vec
+        },
+        PreviewFormat::Apng => {
+            // the delay is stored as a fraction of a second
+            let delay = (100, (options.fps as f64 * 100.0).round().clamp(1.0, u16::MAX as f64) as u16);
+            pngwriter::write_apng(frames, delay, options.loop_count, compression)
+        }
+    }
+}
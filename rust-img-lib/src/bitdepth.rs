@@ -0,0 +1,294 @@
+//! Reduces images to 16 bits per pixel, for devices that use RGBA4444 or RGB565 textures
+
+use image::{Rgba, RgbaImage};
+use wasm_bindgen::prelude::*;
+
+/// The 16-bit pixel formats that can be written
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SixteenBitFormat
+{
+    /// 4 bits per channel, red in the highest bits
+    Rgba4444,
+    /// 5 bits red, 6 bits green, 5 bits blue and no alpha
+    Rgb565
+}
+
+impl SixteenBitFormat
+{
+    /// Bits per channel (red, green, blue, alpha)
+    fn bits(self) -> [u32; 4]
+    {
+        match self {
+            SixteenBitFormat::Rgba4444 => [4, 4, 4, 4],
+            SixteenBitFormat::Rgb565 => [5, 6, 5, 0]
+        }
+    }
+
+    /// Bit masks of the channels (red, green, blue, alpha) within a pixel
+    pub fn masks(self) -> [u32; 4]
+    {
+        match self {
+            SixteenBitFormat::Rgba4444 => [0xf000, 0x0f00, 0x00f0, 0x000f],
+            SixteenBitFormat::Rgb565 => [0xf800, 0x07e0, 0x001f, 0]
+        }
+    }
+}
+
+/// How to hide the banding caused by the lower bit depth
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dithering
+{
+    None,
+    /// 4x4 Bayer matrix. Stays stable between frames of an animation
+    Ordered,
+    /// Floyd-Steinberg
+    ErrorDiffusion
+}
+
+/// Where the reduced spritesheet ends up
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SixteenBitContainer
+{
+    /// A normal PNG showing what the reduced texture looks like
+    PngPreview,
+    /// Just the 16-bit pixels, little endian, row by row
+    Raw,
+    /// An uncompressed 16-bit DDS
+    Dds
+}
+
+/// Settings for writing a spritesheet with 16-bit pixels
+#[derive(Clone, Copy, Debug)]
+pub struct SixteenBitOptions
+{
+    pub format: SixteenBitFormat,
+    pub dithering: Dithering,
+    pub container: SixteenBitContainer
+}
+
+impl SixteenBitOptions
+{
+    pub fn extension(&self) -> &'static str
+    {
+        match self.container {
+            SixteenBitContainer::PngPreview => ".png",
+            SixteenBitContainer::Raw => ".raw",
+            SixteenBitContainer::Dds => ".dds"
+        }
+    }
+}
+
+const BAYER_4X4: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0]
+];
+
+/// Rounds an 8-bit value to `bits` bits
+#[inline]
+fn quantize_channel(value: f32, bits: u32) -> u32
+{
+    let max = ((1 << bits) - 1) as f32;
+    (value.clamp(0.0, 255.0) * max / 255.0).round() as u32
+}
+
+/// Expands a `bits` bit value back to 8 bits by repeating its bits, the same way GPUs do
+#[inline]
+fn expand_channel(value: u32, bits: u32) -> u8
+{
+    let mut expanded = 0;
+    let mut shift = 8i32 - bits as i32;
+    while shift > -(bits as i32)
+    {
+        expanded |= if shift >= 0 { value << shift } else { value >> -shift };
+        shift -= bits as i32;
+    }
+    expanded as u8
+}
+
+/// Reduces every channel of the image to the format's bit depth. Returns the reduced values per pixel (red, green, blue, alpha),
+/// not yet expanded back to 8 bits. Fully transparent pixels stay fully transparent, and never spread or receive any dithering error
+pub fn reduce(img: &RgbaImage, format: SixteenBitFormat, dithering: Dithering) -> Vec<[u32; 4]>
+{
+    let bits = format.bits();
+    let (width, height) = img.dimensions();
+    let mut reduced = Vec::with_capacity((width * height) as usize);
+    // accumulated error for the current and next rows, with a pixel of margin on both sides
+    let row_len = width as usize + 2;
+    let mut errors = vec![[0.0f32; 4]; row_len * 2];
+
+    for y in 0..height
+    {
+        let (cur_row, next_row) = if y % 2 == 0 { (0, row_len) } else { (row_len, 0) };
+        for e in &mut errors[next_row..next_row + row_len]
+        {
+            *e = [0.0; 4];
+        }
+
+        for x in 0..width
+        {
+            let px = img.get_pixel(x, y).0;
+            if px[3] == 0 && bits[3] > 0
+            {
+                reduced.push([0; 4]);
+                continue;
+            }
+
+            let mut wanted = [0.0f32; 4];
+            for (c, w) in wanted.iter_mut().enumerate()
+            {
+                let step = 255.0 / ((1u32 << bits[c]) - 1).max(1) as f32;
+                *w = px[c] as f32 + match dithering {
+                    Dithering::None => 0.0,
+                    Dithering::Ordered => ((BAYER_4X4[y as usize % 4][x as usize % 4] + 0.5) / 16.0 - 0.5) * step,
+                    Dithering::ErrorDiffusion => errors[cur_row + x as usize + 1][c]
+                };
+            }
+            let mut quantized = [0u32; 4];
+            for ((q, &w), &b) in quantized.iter_mut().zip(&wanted).zip(&bits)
+            {
+                *q = if b == 0 { 0 } else { quantize_channel(w, b) };
+            }
+
+            if dithering == Dithering::ErrorDiffusion
+            {
+                let x = x as usize + 1;
+                for c in 0..4
+                {
+                    if bits[c] == 0
+                    {
+                        continue;
+                    }
+                    let err = wanted[c] - expand_channel(quantized[c], bits[c]) as f32;
+                    errors[cur_row + x + 1][c] += err * 7.0 / 16.0;
+                    errors[next_row + x - 1][c] += err * 3.0 / 16.0;
+                    errors[next_row + x][c] += err * 5.0 / 16.0;
+                    errors[next_row + x + 1][c] += err * 1.0 / 16.0;
+                }
+            }
+            reduced.push(quantized);
+        }
+    }
+    reduced
+}
+
+/// What the reduced pixels look like once a GPU expands them back to 8 bits per channel
+pub fn preview(width: u32, height: u32, reduced: &[[u32; 4]], format: SixteenBitFormat) -> RgbaImage
+{
+    let bits = format.bits();
+    let mut img = RgbaImage::new(width, height);
+    for (px, values) in img.pixels_mut().zip(reduced)
+    {
+        let mut channels = [255u8; 4];
+        for ((channel, &value), &b) in channels.iter_mut().zip(values).zip(&bits)
+        {
+            if b > 0
+            {
+                *channel = expand_channel(value, b);
+            }
+        }
+        *px = Rgba(channels);
+    }
+    img
+}
+
+/// Packs the reduced pixels into 16-bit little endian values
+pub fn pack(reduced: &[[u32; 4]], format: SixteenBitFormat) -> Vec<u8>
+{
+    let masks = format.masks();
+    let mut out = Vec::with_capacity(reduced.len() * 2);
+    for values in reduced
+    {
+        let mut packed = 0u32;
+        for (&value, &mask) in values.iter().zip(&masks)
+        {
+            if mask != 0
+            {
+                packed |= value << mask.trailing_zeros();
+            }
+        }
+        out.extend_from_slice(&(packed as u16).to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn reduce_and_pack(pixels: &[[u8; 4]], format: SixteenBitFormat) -> Vec<u16>
+    {
+        let img = RgbaImage::from_fn(pixels.len() as u32, 1, |x, _| Rgba(pixels[x as usize]));
+        pack(&reduce(&img, format, Dithering::None), format)
+            .chunks(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn rgba4444_packs_known_pixels()
+    {
+        let pixels = [[255, 0, 0, 255], [0x11, 0x22, 0x33, 0x44], [255, 255, 255, 128], [9, 9, 9, 0]];
+        assert_eq!(reduce_and_pack(&pixels, SixteenBitFormat::Rgba4444), vec![0xf00f, 0x1234, 0xfff8, 0x0000]);
+    }
+
+    #[test]
+    fn rgb565_packs_known_pixels()
+    {
+        let pixels = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255], [128, 128, 128, 255]];
+        assert_eq!(reduce_and_pack(&pixels, SixteenBitFormat::Rgb565), vec![0xf800, 0x07e0, 0x001f, 0xffff, 0x8410]);
+    }
+
+    #[test]
+    fn packed_values_are_little_endian()
+    {
+        assert_eq!(pack(&[[1, 2, 3, 4]], SixteenBitFormat::Rgba4444), vec![0x34, 0x12]);
+    }
+
+    #[test]
+    fn preview_expands_like_a_gpu()
+    {
+        let reduced = [[0xf, 0x8, 0x0, 0x7], [16, 32, 16, 0]];
+        assert_eq!(preview(1, 1, &reduced[..1], SixteenBitFormat::Rgba4444).get_pixel(0, 0).0, [255, 0x88, 0, 0x77]);
+        assert_eq!(preview(1, 1, &reduced[1..], SixteenBitFormat::Rgb565).get_pixel(0, 0).0, [132, 130, 132, 255]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_color()
+    {
+        // 128 sits between the 4-bit levels 0x77 and 0x88
+        let img = RgbaImage::from_pixel(16, 16, Rgba([128, 128, 128, 255]));
+        let average_red = |dithering: Dithering| {
+            let preview = preview(16, 16, &reduce(&img, SixteenBitFormat::Rgba4444, dithering), SixteenBitFormat::Rgba4444);
+            preview.pixels().map(|px| px.0[0] as f32).sum::<f32>() / 256.0
+        };
+        assert_eq!(average_red(Dithering::None), 136.0);
+        for dithering in [Dithering::Ordered, Dithering::ErrorDiffusion]
+        {
+            let average = average_red(dithering);
+            assert!((average - 128.0).abs() < 2.0, "{:?} averaged {}", dithering, average);
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_get_no_dithering()
+    {
+        let img = RgbaImage::from_fn(8, 8, |x, y| if (x + y) % 2 == 0 { Rgba([0, 0, 0, 0]) } else { Rgba([128, 128, 128, 200]) });
+        for dithering in [Dithering::Ordered, Dithering::ErrorDiffusion]
+        {
+            let reduced = reduce(&img, SixteenBitFormat::Rgba4444, dithering);
+            for (px, values) in img.pixels().zip(&reduced)
+            {
+                if px.0[3] == 0
+                {
+                    assert_eq!(*values, [0; 4]);
+                }
+            }
+        }
+    }
+}
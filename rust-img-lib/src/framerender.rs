@@ -0,0 +1,123 @@
+//! Renders single frames the same way flixel shows them, so that previews can't disagree with what gets exported
+
+use image::{imageops, DynamicImage, RgbaImage};
+use wasm_bindgen::prelude::*;
+
+use crate::{algorithms::spritesheetpackers::growingpacker::ResampleFilter, textureatlas_format::{SubTexture, TextureAtlas}, utils};
+
+/// A rendered frame, as straight (not premultiplied) RGBA pixels, row by row
+#[wasm_bindgen]
+pub struct RenderedFrame
+{
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<u8>
+}
+
+#[wasm_bindgen]
+impl RenderedFrame
+{
+    /// Can be passed straight to `new ImageData(new Uint8ClampedArray(pixels), width, height)`
+    pub fn pixels(&self) -> Vec<u8>
+    {
+        self.pixels.clone()
+    }
+}
+
+impl From<RgbaImage> for RenderedFrame
+{
+    fn from(img: RgbaImage) -> Self
+    {
+        Self { width: img.width(), height: img.height(), pixels: img.into_raw() }
+    }
+}
+
+/// Draws a frame's image inside its frame, using the meaning the XML gives them: the frame is `frame_width`x`frame_height`,
+/// and the image's top left corner sits at (`-frame_x`, `-frame_y`) inside it. Anything that ends up outside of the frame is cut off
+pub fn render(img: Option<&DynamicImage>, frame_x: i64, frame_y: i64, frame_width: u32, frame_height: u32) -> RgbaImage
+{
+    let mut frame = RgbaImage::new(frame_width, frame_height);
+    if let Some(img) = img
+    {
+        imageops::overlay(&mut frame, &img.to_rgba8(), -frame_x, -frame_y);
+    }
+    frame
+}
+
+/// A spritesheet and its XML, loaded so that their frames can be rendered without packing anything
+#[wasm_bindgen]
+pub struct SparrowAtlas
+{
+    image: DynamicImage,
+    atlas: TextureAtlas
+}
+
+#[wasm_bindgen]
+impl SparrowAtlas
+{
+    /// Returns an error if the image can't be loaded or the XML can't be read
+    pub fn new(image_bytes: Vec<u8>, xml: String) -> Result<SparrowAtlas, JsError>
+    {
+        let image = image::load_from_memory(&image_bytes)?;
+        Ok(Self { image, atlas: TextureAtlas::from_xml_string(&xml)? })
+    }
+
+    /// Number of frames in the animation. A frame belongs to an animation if its name is the prefix followed by nothing but digits
+    pub fn frame_count(&self, animation_prefix: String) -> usize
+    {
+        self.animation_frames(&animation_prefix).len()
+    }
+
+    /// Renders a frame of an animation (counting from 0, in the order of the numbers in the frame names) like flixel does, with its flips and rotation.
+    /// `scale` resizes the whole frame, with nearest neighbour unless `filter` is given. Returns nothing if there's no such frame, and an error if the scale isn't positive
+    pub fn render_frame(&self, animation_prefix: String, index: usize, scale: f32, filter: Option<ResampleFilter>) -> Result<Option<RenderedFrame>, JsError>
+    {
+        if !(scale.is_finite() && scale > 0.0)
+        {
+            return Err(JsError::new(&format!("The scale must be positive, got {}", scale)));
+        }
+        let subtexture = match self.animation_frames(&animation_prefix).get(index) {
+            Some(&subtexture) => subtexture,
+            None => return Ok(None)
+        };
+
+        let (frame_x, frame_y, frame_width, frame_height) = subtexture.frame_rect();
+        let mut frame = DynamicImage::ImageRgba8(render(Some(&subtexture.upright_image(&self.image)), frame_x as i64, frame_y as i64, frame_width, frame_height));
+        if subtexture.flip_x == Some(true)
+        {
+            frame = frame.fliph();
+        }
+        if subtexture.flip_y == Some(true)
+        {
+            frame = frame.flipv();
+        }
+        if scale != 1.0
+        {
+            let width = ((frame.width() as f64 * scale as f64).round() as u32).max(1);
+            let height = ((frame.height() as f64 * scale as f64).round() as u32).max(1);
+            frame = utils::resize_image(&frame, width, height, filter.unwrap_or(ResampleFilter::Nearest).into());
+        }
+        Ok(Some(frame.to_rgba8().into()))
+    }
+}
+
+impl SparrowAtlas
+{
+    /// The frames of an animation, ordered by the numbers at the end of their names
+    fn animation_frames(&self, animation_prefix: &str) -> Vec<&SubTexture>
+    {
+        let mut frames: Vec<(u64, &SubTexture)> = self.atlas.subtextures
+            .iter()
+            .filter_map(|subtexture| {
+                let suffix = subtexture.name.strip_prefix(animation_prefix)?;
+                if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit())
+                {
+                    return None;
+                }
+                Some((suffix.parse().unwrap_or(u64::MAX), subtexture))
+            })
+            .collect();
+        frames.sort_by_key(|(number, _)| *number);
+        frames.into_iter().map(|(_, subtexture)| subtexture).collect()
+    }
+}
@@ -1,10 +1,19 @@
 mod utils;
 mod algorithms;
 mod textureatlas_format;
+mod quantize;
+mod pngwriter;
+mod texturewriter;
+mod bitdepth;
+mod modbundle;
+mod progress;
+mod animpreview;
+mod framerender;
+mod zipimport;
 
 use base64::Engine;
 use image::{imageops, GenericImageView};
-use utils::{set_panic_hook, encode_image_as_png};
+use utils::{set_panic_hook, encode_image_as_png, PngOptions};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -92,7 +101,7 @@ pub fn make_icongrid_legacy(icongrid: Vec<u8>, icons: js_sys::Array) -> Vec<u8>
         imageops::overlay(&mut icongrid_img, &icon, insertion_x as i64, insertion_y as i64);
     }
 
-    encode_image_as_png(&icongrid_img)
+    encode_image_as_png(&icongrid_img, &PngOptions::default())
 }
 
 // #[wasm_bindgen]
@@ -0,0 +1,91 @@
+//! Zip layouts that match the folder structure of FNF mods, along with the character files those mods expect
+
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+/// Where `make_packed_image` puts files inside the zip
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZipLayout
+{
+    /// `<name>.png` and `<name>.xml` at the root of the zip
+    #[default]
+    Flat,
+    /// `images/characters/<name>.png` and `.xml`, plus a Psych Engine character file at `characters/<name>.json`
+    PsychEngine,
+    /// `images/characters/<name>.png` and `.xml`, plus a V-Slice character file at `data/characters/<name>.json`
+    VSlice
+}
+
+impl ZipLayout
+{
+    pub fn default_image_folder(self) -> &'static str
+    {
+        match self {
+            ZipLayout::Flat => "",
+            ZipLayout::PsychEngine | ZipLayout::VSlice => "images/characters/"
+        }
+    }
+}
+
+/// Makes sure a folder inside the zip is either empty (the root) or ends with a single `/`
+pub fn normalize_folder(folder: &str) -> String
+{
+    let trimmed = folder.trim_matches('/');
+    if trimmed.is_empty() { String::new() } else { format!("{}/", trimmed) }
+}
+
+/// The path engines use to load an image: relative to the `images` folder (e.g. `shared/images/`) and without an extension
+fn asset_path(image_folder: &str, atlas_name: &str) -> String
+{
+    let folder = match image_folder.find("images/") {
+        Some(i) if i == 0 || image_folder[..i].ends_with('/') => &image_folder[i + "images/".len()..],
+        _ => image_folder
+    };
+    format!("{}{}", folder, atlas_name)
+}
+
+/// Writes the character file for the layout (if it has one), with one animation per prefix in the order given. Returns its path in the zip along with its contents.
+///
+/// Offsets, frame rates and the like are left at their defaults, to be tweaked in the engine's character editor
+pub fn character_file(layout: ZipLayout, character_name: &str, image_folder: &str, animation_prefixes: &[&str]) -> Option<(String, Vec<u8>)>
+{
+    let asset_path = asset_path(image_folder, character_name);
+    let (path, character) = match layout {
+        ZipLayout::Flat => return None,
+        ZipLayout::PsychEngine => (format!("characters/{}.json", character_name), json!({
+            "animations": animation_prefixes.iter().map(|prefix| json!({
+                "anim": prefix,
+                "name": prefix,
+                "fps": 24,
+                "loop": false,
+                "indices": [],
+                "offsets": [0, 0]
+            })).collect::<Vec<_>>(),
+            "image": asset_path,
+            "scale": 1,
+            "sing_duration": 4,
+            "healthicon": "face",
+            "position": [0, 0],
+            "camera_position": [0, 0],
+            "flip_x": false,
+            "no_antialiasing": false,
+            "healthbar_colors": [161, 161, 161]
+        })),
+        ZipLayout::VSlice => (format!("data/characters/{}.json", character_name), json!({
+            "version": "1.0.0",
+            "name": character_name,
+            "renderType": "sparrow",
+            "assetPath": asset_path,
+            "scale": 1.0,
+            "animations": animation_prefixes.iter().map(|prefix| json!({
+                "name": prefix,
+                "prefix": prefix,
+                "offsets": [0, 0],
+                "frameRate": 24,
+                "looped": false
+            })).collect::<Vec<_>>()
+        }))
+    };
+    Some((path, serde_json::to_vec_pretty(&character).expect("Could not write character json!")))
+}
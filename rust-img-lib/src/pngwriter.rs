@@ -0,0 +1,261 @@
+//! Low level PNG writing, and the lossless optimization pass for finished spritesheets
+
+use std::{collections::HashSet, convert::TryInto};
+
+use image::codecs::png::{CompressionType, FilterType};
+use wasm_bindgen::prelude::*;
+
+use crate::quantize::{self, QuantizedImage, Quantization};
+
+/// How hard zlib tries when compressing the image data
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PngCompression
+{
+    #[default]
+    Fast,
+    Default,
+    Best
+}
+
+impl From<PngCompression> for CompressionType
+{
+    fn from(value: PngCompression) -> Self
+    {
+        match value {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best
+        }
+    }
+}
+
+impl From<PngCompression> for png::Compression
+{
+    fn from(value: PngCompression) -> Self
+    {
+        match value {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best
+        }
+    }
+}
+
+/// The filter applied to each row before compressing it
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PngFilter
+{
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks the best filter for every row
+    #[default]
+    Adaptive
+}
+
+impl From<PngFilter> for FilterType
+{
+    fn from(value: PngFilter) -> Self
+    {
+        match value {
+            PngFilter::NoFilter => FilterType::NoFilter,
+            PngFilter::Sub => FilterType::Sub,
+            PngFilter::Up => FilterType::Up,
+            PngFilter::Avg => FilterType::Avg,
+            PngFilter::Paeth => FilterType::Paeth,
+            PngFilter::Adaptive => FilterType::Adaptive
+        }
+    }
+}
+
+/// Raw 8-bit pixel data, ready to be written as a PNG
+pub struct PngImageData
+{
+    pub width: u32,
+    pub height: u32,
+    pub color: png::ColorType,
+    pub data: Vec<u8>,
+    /// RGB triplets, only used for indexed images
+    pub palette: Option<Vec<u8>>,
+    pub trns: Option<Vec<u8>>
+}
+
+impl PngImageData
+{
+    pub fn rgba(img: &image::RgbaImage) -> Self
+    {
+        Self { width: img.width(), height: img.height(), color: png::ColorType::Rgba, data: img.as_raw().clone(), palette: None, trns: None }
+    }
+
+    pub fn indexed(quantized: &QuantizedImage) -> Self
+    {
+        // entries after the last translucent one can be left out of tRNS
+        let alphas: Vec<u8> = quantized.palette.iter().map(|c| c[3]).collect();
+        let trns_len = alphas.iter().rposition(|&a| a != 255).map(|i| i + 1).unwrap_or(0);
+        Self {
+            width: quantized.width,
+            height: quantized.height,
+            color: png::ColorType::Indexed,
+            data: quantized.indices.clone(),
+            palette: Some(quantized.palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect()),
+            trns: if trns_len > 0 { Some(alphas[..trns_len].to_vec()) } else { None }
+        }
+    }
+}
+
+pub fn write_png(img: &PngImageData, compression: PngCompression, filter: PngFilter) -> Vec<u8>
+{
+    let mut out_vec = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out_vec, img.width, img.height);
+        encoder.set_color(img.color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(compression.into());
+        let (filter, adaptive) = match filter {
+            PngFilter::NoFilter => (png::FilterType::NoFilter, png::AdaptiveFilterType::NonAdaptive),
+            PngFilter::Sub => (png::FilterType::Sub, png::AdaptiveFilterType::NonAdaptive),
+            PngFilter::Up => (png::FilterType::Up, png::AdaptiveFilterType::NonAdaptive),
+            PngFilter::Avg => (png::FilterType::Avg, png::AdaptiveFilterType::NonAdaptive),
+            PngFilter::Paeth => (png::FilterType::Paeth, png::AdaptiveFilterType::NonAdaptive),
+            PngFilter::Adaptive => (png::FilterType::Sub, png::AdaptiveFilterType::Adaptive)
+        };
+        encoder.set_filter(filter);
+        encoder.set_adaptive_filter(adaptive);
+        if let Some(palette) = &img.palette
+        {
+            encoder.set_palette(palette.as_slice());
+        }
+        if let Some(trns) = &img.trns
+        {
+            encoder.set_trns(trns.as_slice());
+        }
+
+        let mut writer = encoder.write_header().expect("Error writing png header!");
+        writer.write_image_data(&img.data).expect("Error writing png to buffer!");
+    }
+    out_vec
+}
+
+/// Writes the frames (which must all be the same size) as an APNG. Each frame is shown for `delay` (numerator, denominator) seconds, and the
+/// animation plays `plays` times (`0` loops forever)
+pub fn write_apng(frames: &[image::RgbaImage], delay: (u16, u16), plays: u32, compression: PngCompression) -> Vec<u8>
+{
+    let (width, height) = frames.first().map(|frame| frame.dimensions()).unwrap_or((1, 1));
+    let mut out_vec = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out_vec, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(compression.into());
+        encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+        encoder.set_animated(frames.len().try_into().expect("Too many frames for an APNG!"), plays).expect("Error setting up APNG!");
+        encoder.set_frame_delay(delay.0, delay.1).expect("Error setting APNG frame delay!");
+
+        let mut writer = encoder.write_header().expect("Error writing png header!");
+        for frame in frames
+        {
+            writer.write_image_data(frame.as_raw()).expect("Error writing APNG frame!");
+        }
+        writer.finish().expect("Error finishing APNG!");
+    }
+    out_vec
+}
+
+const FILTER_CANDIDATES: [PngFilter; 6] = [
+    PngFilter::NoFilter,
+    PngFilter::Sub,
+    PngFilter::Up,
+    PngFilter::Avg,
+    PngFilter::Paeth,
+    PngFilter::Adaptive
+];
+
+/// Tries every lossless color type reduction of the image and every filter strategy, and returns the smallest PNG found.
+///
+/// `baseline` (the PNG that was written without optimizing) is returned if nothing beats it, so the result never grows
+pub fn optimize(img: &PngImageData, baseline: Vec<u8>) -> Vec<u8>
+{
+    let mut best = strip_ancillary_chunks(&baseline).unwrap_or(baseline);
+    for candidate in lossless_reductions(img).iter().chain(std::iter::once(img))
+    {
+        for filter in FILTER_CANDIDATES
+        {
+            let encoded = write_png(candidate, PngCompression::Best, filter);
+            if encoded.len() < best.len()
+            {
+                best = encoded;
+            }
+        }
+    }
+    best
+}
+
+/// Smaller representations of an RGBA image that decode to exactly the same pixels
+fn lossless_reductions(img: &PngImageData) -> Vec<PngImageData>
+{
+    if img.color != png::ColorType::Rgba
+    {
+        return vec![];
+    }
+
+    let pixels: Vec<&[u8]> = img.data.chunks_exact(4).collect();
+    let is_opaque = pixels.iter().all(|px| px[3] == 255);
+    let is_gray = pixels.iter().all(|px| px[0] == px[1] && px[1] == px[2]);
+    let mut reductions = vec![];
+
+    let reduced_color = match (is_gray, is_opaque) {
+        (true, true) => Some((png::ColorType::Grayscale, pixels.iter().map(|px| px[0]).collect())),
+        (true, false) => Some((png::ColorType::GrayscaleAlpha, pixels.iter().flat_map(|px| [px[0], px[3]]).collect())),
+        (false, true) => Some((png::ColorType::Rgb, pixels.iter().flat_map(|px| [px[0], px[1], px[2]]).collect())),
+        (false, false) => None
+    };
+    if let Some((color, data)) = reduced_color
+    {
+        reductions.push(PngImageData { width: img.width, height: img.height, color, data, palette: None, trns: None });
+    }
+
+    // fully transparent pixels are all the same once written, whatever their color channels say
+    let unique_colors: HashSet<[u8; 4]> = pixels
+        .iter()
+        .map(|px| if px[3] == 0 { [0, 0, 0, 0] } else { [px[0], px[1], px[2], px[3]] })
+        .collect();
+    if unique_colors.len() <= 256 && !is_gray
+    {
+        if let Some(rgba_img) = image::RgbaImage::from_raw(img.width, img.height, img.data.clone())
+        {
+            let (quantized, report) = quantize::quantize(&rgba_img, Quantization { max_colors: 256, dither: false });
+            if report.mean_squared_error == 0.0
+            {
+                reductions.push(PngImageData::indexed(&quantized));
+            }
+        }
+    }
+    reductions
+}
+
+/// Chunks that affect how the pixels are decoded. Everything else can be dropped
+const NEEDED_CHUNKS: [&[u8; 4]; 5] = [b"IHDR", b"PLTE", b"tRNS", b"IDAT", b"IEND"];
+
+/// Removes every chunk not in `NEEDED_CHUNKS`. Returns `None` if the PNG couldn't be parsed
+fn strip_ancillary_chunks(png_bytes: &[u8]) -> Option<Vec<u8>>
+{
+    const SIGNATURE_LEN: usize = 8;
+    let mut out = png_bytes.get(..SIGNATURE_LEN)?.to_vec();
+    let mut pos = SIGNATURE_LEN;
+    while pos < png_bytes.len()
+    {
+        let length = u32::from_be_bytes(png_bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        // length + type + data + crc
+        let chunk = png_bytes.get(pos..pos + 12 + length)?;
+        if NEEDED_CHUNKS.iter().any(|name| &chunk[4..8] == name.as_slice())
+        {
+            out.extend_from_slice(chunk);
+        }
+        pos += 12 + length;
+    }
+    Some(out)
+}
@@ -0,0 +1,96 @@
+//! Progress reporting and cancellation for long running exports
+
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+
+/// Lets JS stop an export part way through. Cancel it from inside the progress callback, or between calls to `export_step`
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken
+{
+    cancelled: Rc<Cell<bool>>
+}
+
+#[wasm_bindgen]
+impl CancellationToken
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn cancel(&self)
+    {
+        self.cancelled.set(true);
+    }
+
+    /// Un-cancels the token so it can be used for another export
+    pub fn reset(&self)
+    {
+        self.cancelled.set(false);
+    }
+
+    pub fn is_cancelled(&self) -> bool
+    {
+        self.cancelled.get()
+    }
+}
+
+/// Returned (thrown, on the JS side) when an export is stopped by its `CancellationToken`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The export was cancelled")
+    }
+}
+impl std::error::Error for Cancelled {}
+
+impl From<Cancelled> for JsValue
+{
+    fn from(cancelled: Cancelled) -> Self
+    {
+        js_sys::Error::new(&cancelled.to_string()).into()
+    }
+}
+
+/// The JS progress callback and cancellation token of a packer. Both are optional
+#[derive(Clone, Default)]
+pub struct ProgressReporter
+{
+    /// Called as `callback(phase, done, total)`
+    callback: Option<js_sys::Function>,
+    token: Option<CancellationToken>
+}
+
+impl ProgressReporter
+{
+    pub fn set_callback(&mut self, callback: Option<js_sys::Function>)
+    {
+        self.callback = callback;
+    }
+
+    pub fn set_token(&mut self, token: Option<CancellationToken>)
+    {
+        self.token = token;
+    }
+
+    /// Errors thrown by the callback are ignored, so a broken progress bar can't break the export
+    pub fn report(&self, phase: &str, done: usize, total: usize)
+    {
+        if let Some(callback) = &self.callback
+        {
+            let _ = callback.call3(&JsValue::NULL, &JsValue::from_str(phase), &JsValue::from(done as u32), &JsValue::from(total as u32));
+        }
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), Cancelled>
+    {
+        match &self.token {
+            Some(token) if token.is_cancelled() => Err(Cancelled),
+            _ => Ok(())
+        }
+    }
+}
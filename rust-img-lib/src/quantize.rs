@@ -0,0 +1,330 @@
+//! Reduces images to a palette of at most 256 colors so they can be written as indexed PNGs
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+/// Settings for palette quantization
+#[derive(Clone, Copy, Debug)]
+pub struct Quantization
+{
+    /// Between 2 and 256 (inclusive)
+    pub max_colors: u16,
+    /// Use Floyd-Steinberg dithering to hide banding
+    pub dither: bool
+}
+
+/// How much the quantized image differs from the original
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QuantizationReport
+{
+    /// Number of colors in the final palette
+    pub colors: u32,
+    /// Mean squared error over all RGBA channels
+    pub mean_squared_error: f64,
+    /// The largest difference seen in any single channel
+    pub max_error: u8
+}
+
+#[wasm_bindgen]
+impl QuantizationReport
+{
+    /// Peak signal-to-noise ratio in decibels (infinite if nothing changed)
+    pub fn psnr(&self) -> f64
+    {
+        10.0 * (255.0 * 255.0 / self.mean_squared_error).log10()
+    }
+}
+
+pub struct QuantizedImage
+{
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<[u8; 4]>,
+    /// One palette index per pixel
+    pub indices: Vec<u8>
+}
+
+/// Fully transparent pixels all become the same color, so that they share a single palette entry
+#[inline]
+fn normalize(px: [u8; 4]) -> [u8; 4]
+{
+    if px[3] == 0 { [0, 0, 0, 0] } else { px }
+}
+
+pub fn quantize(img: &image::RgbaImage, options: Quantization) -> (QuantizedImage, QuantizationReport)
+{
+    let max_colors = options.max_colors.clamp(2, 256) as usize;
+
+    let mut color_counts: HashMap<[u8; 4], u32> = HashMap::new();
+    for px in img.pixels()
+    {
+        *color_counts.entry(normalize(px.0)).or_insert(0) += 1;
+    }
+
+    let is_exact = color_counts.len() <= max_colors;
+    let palette: Vec<[u8; 4]> = if is_exact {
+        let mut palette: Vec<[u8; 4]> = color_counts.keys().copied().collect();
+        palette.sort_unstable();
+        palette
+    } else {
+        // transparency always gets its own entry so it never picks up a color
+        let has_transparency = color_counts.remove(&[0, 0, 0, 0]).is_some();
+        let mut palette = median_cut(color_counts.into_iter().collect(), max_colors - has_transparency as usize);
+        if has_transparency
+        {
+            palette.insert(0, [0, 0, 0, 0]);
+        }
+        palette
+    };
+
+    let mut nearest_cache: HashMap<[u8; 4], u8> = palette.iter().enumerate().map(|(i, &c)| (c, i as u8)).collect();
+    let mut nearest = |color: [u8; 4]| -> u8 {
+        *nearest_cache.entry(color).or_insert_with(|| {
+            palette
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, &p)| (0..4).map(|c| (p[c] as i32 - color[c] as i32).pow(2)).sum::<i32>())
+                .map(|(i, _)| i as u8)
+                .unwrap_or(0)
+        })
+    };
+
+    let (width, height) = img.dimensions();
+    let mut indices = vec![0u8; (width * height) as usize];
+    // accumulated dithering error for the current and next rows
+    let mut errors = vec![[0.0f32; 4]; (width as usize + 2) * 2];
+    let row_len = width as usize + 2;
+    for y in 0..height
+    {
+        let (cur_row, next_row) = if y % 2 == 0 { (0, row_len) } else { (row_len, 0) };
+        for e in &mut errors[next_row..next_row + row_len]
+        {
+            *e = [0.0; 4];
+        }
+        for x in 0..width
+        {
+            let original = normalize(img.get_pixel(x, y).0);
+            let wanted = if options.dither && !is_exact && original[3] != 0 {
+                let err = errors[cur_row + x as usize + 1];
+                let mut c = [0u8; 4];
+                for i in 0..4
+                {
+                    c[i] = (original[i] as f32 + err[i]).round().clamp(0.0, 255.0) as u8;
+                }
+                c
+            } else {
+                original
+            };
+            let idx = nearest(normalize(wanted));
+            indices[(y * width + x) as usize] = idx;
+
+            if options.dither && !is_exact && original[3] != 0
+            {
+                let chosen = palette[idx as usize];
+                let x = x as usize + 1;
+                for i in 0..4
+                {
+                    let err = wanted[i] as f32 - chosen[i] as f32;
+                    errors[cur_row + x + 1][i] += err * 7.0 / 16.0;
+                    errors[next_row + x - 1][i] += err * 3.0 / 16.0;
+                    errors[next_row + x][i] += err * 5.0 / 16.0;
+                    errors[next_row + x + 1][i] += err * 1.0 / 16.0;
+                }
+            }
+        }
+    }
+
+    let mut squared_error = 0.0f64;
+    let mut max_error = 0u8;
+    for (px, &idx) in img.pixels().zip(&indices)
+    {
+        let (original, quantized) = (normalize(px.0), palette[idx as usize]);
+        for c in 0..4
+        {
+            let diff = original[c].abs_diff(quantized[c]);
+            squared_error += (diff as f64).powi(2);
+            max_error = max_error.max(diff);
+        }
+    }
+    let channel_count = (indices.len() * 4).max(1) as f64;
+
+    let report = QuantizationReport {
+        colors: palette.len() as u32,
+        mean_squared_error: squared_error / channel_count,
+        max_error
+    };
+    (QuantizedImage { width, height, palette, indices }, report)
+}
+
+/// Splits the colors into at most `max_colors` boxes, and returns the (weighted) average color of each box
+fn median_cut(colors: Vec<([u8; 4], u32)>, max_colors: usize) -> Vec<[u8; 4]>
+{
+    if colors.is_empty()
+    {
+        return vec![];
+    }
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors
+    {
+        // split the box with the widest channel range, weighted by how many pixels it covers
+        let to_split = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .max_by_key(|(_, b)| {
+                let (_, range) = widest_channel(b);
+                range as u64 * b.iter().map(|(_, count)| *count as u64).sum::<u64>()
+            })
+            .map(|(i, _)| i);
+
+        let Some(i) = to_split else { break };
+        let mut colors = boxes.swap_remove(i);
+        let (channel, _) = widest_channel(&colors);
+        colors.sort_unstable_by_key(|(c, _)| c[channel]);
+
+        let total: u64 = colors.iter().map(|(_, count)| *count as u64).sum();
+        let mut running = 0;
+        let mut split_at = colors.len() / 2;
+        for (j, (_, count)) in colors.iter().enumerate()
+        {
+            running += *count as u64;
+            if running * 2 >= total
+            {
+                split_at = j + 1;
+                break;
+            }
+        }
+        let split_at = split_at.clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split_at);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|b| {
+            let total: u64 = b.iter().map(|(_, count)| *count as u64).sum();
+            let mut avg = [0u8; 4];
+            for (c, channel_avg) in avg.iter_mut().enumerate()
+            {
+                let sum: u64 = b.iter().map(|(color, count)| color[c] as u64 * *count as u64).sum();
+                *channel_avg = ((sum + total / 2) / total) as u8;
+            }
+            avg
+        })
+        .collect()
+}
+
+/// Returns the channel with the largest range of values, along with that range
+fn widest_channel(colors: &[([u8; 4], u32)]) -> (usize, u8)
+{
+    (0..4)
+        .map(|c| {
+            let min = colors.iter().map(|(color, _)| color[c]).min().unwrap_or(0);
+            let max = colors.iter().map(|(color, _)| color[c]).max().unwrap_or(0);
+            (c, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> image::RgbaImage
+    {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let alpha = if x == 0 { 0 } else { 255 };
+            image::Rgba([(x * 255 / width) as u8, (y * 255 / height) as u8, ((x + y) * 2) as u8, alpha])
+        })
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly()
+    {
+        let img = image::RgbaImage::from_fn(8, 8, |x, y| image::Rgba([(x % 3) as u8 * 100, (y % 2) as u8 * 200, 50, 255]));
+        let (quantized, report) = quantize(&img, Quantization { max_colors: 16, dither: true });
+
+        assert_eq!(report.colors, 6);
+        assert_eq!(report.mean_squared_error, 0.0);
+        assert_eq!(report.max_error, 0);
+        for (px, &idx) in img.pixels().zip(&quantized.indices)
+        {
+            assert_eq!(quantized.palette[idx as usize], px.0);
+        }
+    }
+
+    #[test]
+    fn report_matches_the_palette_error()
+    {
+        let img = gradient(64, 64);
+        for &dither in &[false, true]
+        {
+            let (quantized, report) = quantize(&img, Quantization { max_colors: 16, dither });
+            assert!(quantized.palette.len() <= 16);
+            assert_eq!(report.colors as usize, quantized.palette.len());
+
+            let mut squared_error = 0.0;
+            let mut max_error = 0;
+            for (px, &idx) in img.pixels().zip(&quantized.indices)
+            {
+                let (original, chosen) = (normalize(px.0), quantized.palette[idx as usize]);
+                for c in 0..4
+                {
+                    let diff = original[c].abs_diff(chosen[c]);
+                    squared_error += (diff as f64).powi(2);
+                    max_error = max_error.max(diff);
+                }
+            }
+            assert_eq!(report.max_error, max_error);
+            assert!((report.mean_squared_error - squared_error / (64.0 * 64.0 * 4.0)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn undithered_pixels_use_their_nearest_palette_color()
+    {
+        let img = gradient(64, 64);
+        let (quantized, _) = quantize(&img, Quantization { max_colors: 16, dither: false });
+        let distance = |a: [u8; 4], b: [u8; 4]| (0..4).map(|c| (a[c] as i32 - b[c] as i32).pow(2)).sum::<i32>();
+        for (px, &idx) in img.pixels().zip(&quantized.indices)
+        {
+            let original = normalize(px.0);
+            let best = quantized.palette.iter().map(|&p| distance(original, p)).min().unwrap();
+            assert_eq!(distance(original, quantized.palette[idx as usize]), best);
+        }
+    }
+
+    #[test]
+    fn error_shrinks_with_more_colors()
+    {
+        let img = gradient(64, 64);
+        let reports: Vec<QuantizationReport> =
+            [16, 64, 256].iter().map(|&max_colors| quantize(&img, Quantization { max_colors, dither: false }).1).collect();
+        for pair in reports.windows(2)
+        {
+            assert!(pair[1].mean_squared_error < pair[0].mean_squared_error);
+            assert!(pair[1].max_error <= pair[0].max_error);
+        }
+        // a full palette over a 4096 color gradient stays within a few steps of every original
+        let full = reports.last().unwrap();
+        assert!(full.max_error <= 16, "max error was {}", full.max_error);
+        assert!(full.psnr() > 35.0, "psnr was {}", full.psnr());
+    }
+
+    #[test]
+    fn transparency_keeps_its_own_entry()
+    {
+        let img = gradient(64, 64);
+        let (quantized, _) = quantize(&img, Quantization { max_colors: 8, dither: true });
+        for (px, &idx) in img.pixels().zip(&quantized.indices)
+        {
+            assert_eq!(px.0[3] == 0, quantized.palette[idx as usize] == [0, 0, 0, 0]);
+        }
+    }
+}
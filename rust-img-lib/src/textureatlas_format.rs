@@ -1,8 +1,10 @@
-use std::{str::FromStr, fmt::Debug, path::Path, io::Write};
+use std::{convert::TryFrom, str::FromStr, fmt::Debug, path::Path, io::Write};
 
 #[allow(unused_imports)]
 use quick_xml::{Reader, events::{Event, attributes::Attribute, BytesStart, BytesDecl, BytesText, BytesEnd}, Writer};
 
+use image::DynamicImage;
+
 use crate::utils::PrefixCounter;
 
 #[allow(dead_code)]
@@ -11,6 +13,55 @@ fn parse_value<T: FromStr>(att: &Attribute) -> T where <T as FromStr>::Err: Debu
     String::from_utf8(att.value.as_ref().to_vec()).unwrap().parse().unwrap()
 }
 
+/// Reads a number attribute, rounding any decimals away. Fails if it isn't a number, or doesn't fit in `T` (e.g. a negative width)
+fn parse_number<T: TryFrom<i64>>(att: &Attribute, value: &str) -> Result<T, AtlasParseError>
+{
+    let invalid = || AtlasParseError::InvalidNumber {
+        attribute: String::from_utf8_lossy(att.key.as_ref()).into_owned(),
+        value: value.to_string()
+    };
+    let number: f64 = value.trim().parse().map_err(|_| invalid())?;
+    if !number.is_finite() || number.abs() > i64::MAX as f64
+    {
+        return Err(invalid());
+    }
+    T::try_from(number.round() as i64).map_err(|_| invalid())
+}
+
+/// Why a texture atlas XML couldn't be read
+#[derive(Debug)]
+pub enum AtlasParseError
+{
+    Xml(quick_xml::Error),
+    InvalidNumber { attribute: String, value: String }
+}
+
+impl std::fmt::Display for AtlasParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtlasParseError::Xml(err) => write!(f, "The XML could not be read: {}", err),
+            AtlasParseError::InvalidNumber { attribute, value } => write!(f, "The XML has {}=\"{}\", which isn't a valid number there", attribute, value)
+        }
+    }
+}
+impl std::error::Error for AtlasParseError {}
+
+impl From<quick_xml::Error> for AtlasParseError
+{
+    fn from(err: quick_xml::Error) -> Self
+    {
+        AtlasParseError::Xml(err)
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for AtlasParseError
+{
+    fn from(err: quick_xml::events::attributes::AttrError) -> Self
+    {
+        AtlasParseError::Xml(err.into())
+    }
+}
+
 fn zero_fill_num(num: u32, to_len: usize) -> Result<String, String>
 {
     let num_string = num.to_string();
@@ -98,8 +149,8 @@ impl TextureAtlas
         Self { image_path: img_path, subtextures }
     }
 
-    #[cfg(not(target_arch="wasm32"))]
-    pub fn from_xml_string(xmlstr: &str) -> Self
+    /// Reads a Sparrow XML the way flixel does: numbers may have decimals (they're rounded), and flips and rotation are only on when they're `"true"`
+    pub fn from_xml_string(xmlstr: &str) -> Result<Self, AtlasParseError>
     {
         let mut reader = Reader::from_str(xmlstr);
         reader.trim_text(true);
@@ -107,53 +158,68 @@ impl TextureAtlas
         let mut img_path: String = String::from("");
         let mut subtextures = vec![];
         loop {
-            match reader.read_event() {
-                Ok(Event::Eof) => break,
+            match reader.read_event()? {
+                Event::Eof => break,
                 
-                Ok(Event::Start(e)) => {
-                    match e.name().as_ref() {
-                        b"TextureAtlas" => {
-                            let imgname = e.attributes().find(|item| item.as_ref().unwrap().key.0 == b"imagePath");
-                            img_path = String::from_utf8(imgname.unwrap().unwrap().value.as_ref().to_vec()).unwrap();
-                        },
-                        _ => ()
+                Event::Start(e) if e.name().as_ref() == b"TextureAtlas" => {
+                    for att in e.attributes()
+                    {
+                        let att = att?;
+                        if att.key.as_ref() == b"imagePath"
+                        {
+                            img_path = att.unescape_value()?.into_owned();
+                        }
                     }
                 },
 
-                Ok(Event::Empty(e)) => {
-                    if e.name().as_ref() == b"SubTexture"
+                Event::Empty(e) if e.name().as_ref() == b"SubTexture" => {
+                    let mut st = SubTexture::default();
+                    for att in e.attributes()
                     {
-                        let mut st = SubTexture::default();
-                        for att in e.attributes()
-                        {
-                            let att = att.unwrap();
-                            match att.key.as_ref() {
-                                b"name" => st.name = String::from_utf8(att.value.as_ref().to_vec()).unwrap(),
-                                b"x" => st.x = parse_value(&att),
-                                b"y" => st.y = parse_value(&att),
-                                b"width" => st.width = parse_value(&att),
-                                b"height" => st.height = parse_value(&att),
-                                b"frameX" => st.frame_x = Some(parse_value(&att)),
-                                b"frameY" => st.frame_y = Some(parse_value(&att)),
-                                b"frameWidth" => st.frame_width = Some(parse_value(&att)),
-                                b"frameHeight" => st.frame_height = Some(parse_value(&att)),
-                                _ => ()
-                            }
+                        let att = att?;
+                        let value = att.unescape_value()?;
+                        match att.key.as_ref() {
+                            b"name" => st.name = value.into_owned(),
+                            b"x" => st.x = parse_number(&att, &value)?,
+                            b"y" => st.y = parse_number(&att, &value)?,
+                            b"width" => st.width = parse_number(&att, &value)?,
+                            b"height" => st.height = parse_number(&att, &value)?,
+                            b"frameX" => st.frame_x = Some(parse_number(&att, &value)?),
+                            b"frameY" => st.frame_y = Some(parse_number(&att, &value)?),
+                            b"frameWidth" => st.frame_width = Some(parse_number(&att, &value)?),
+                            b"frameHeight" => st.frame_height = Some(parse_number(&att, &value)?),
+                            b"flipX" => st.flip_x = Some(value == "true"),
+                            b"flipY" => st.flip_y = Some(value == "true"),
+                            b"rotated" => st.rotated = value == "true",
+                            _ => ()
                         }
-                        subtextures.push(st);
                     }
+                    subtextures.push(st);
                 },
 
                 _ => ()
             }
         }
 
-        Self { image_path: img_path, subtextures }
+        Ok(Self { image_path: img_path, subtextures })
     }
 
-    pub fn write_to<W: Write>(&self, writer: W)
+    /// The names the subtextures are written with: their animation prefix, followed by their (zero-padded) index within the animation
+    pub fn subtexture_names(&self) -> Vec<String>
     {
         let mut prefix_counter: PrefixCounter = PrefixCounter::new();
+        self.subtextures
+            .iter()
+            .map(|thing| {
+                let anim_suffix_num = prefix_counter.add_prefix(&thing.name);
+                let suffix_num = zero_fill_num(anim_suffix_num, 4).unwrap_or(anim_suffix_num.to_string());
+                format!("{}{}", thing.name, suffix_num)
+            })
+            .collect()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W)
+    {
         let mut wr = Writer::new_with_indent(writer, '\t' as u8, 1);
         
         // xml decl
@@ -168,13 +234,10 @@ impl TextureAtlas
         wr.write_event(Event::Comment(BytesText::new(" Created using the Spritesheet and XML generator "))).unwrap();
         wr.write_event(Event::Comment(BytesText::new(" https://uncertainprod.github.io/FNF-Spritesheet-XML-generator-Web "))).unwrap();
 
-        for thing in &self.subtextures
+        for (thing, name) in self.subtextures.iter().zip(self.subtexture_names())
         {
-            let anim_suffix_num = prefix_counter.add_prefix(&thing.name);
-
             let mut bys = BytesStart::new("SubTexture");
-            let suffix_num = zero_fill_num(anim_suffix_num, 4).unwrap_or(anim_suffix_num.to_string());
-            bys.push_attribute(("name", format!("{}{}", thing.name, suffix_num).as_str()));
+            bys.push_attribute(("name", name.as_str()));
             bys.push_attribute(("x", thing.x.to_string().as_str()));
             bys.push_attribute(("y", thing.y.to_string().as_str()));
             bys.push_attribute(("width", thing.width.to_string().as_str()));
@@ -221,7 +284,9 @@ pub struct SubTexture
     pub frame_width: Option<u32>,
     pub frame_height: Option<u32>,
     pub flip_x: Option<bool>,
-    pub flip_y: Option<bool>
+    pub flip_y: Option<bool>,
+    /// The image is stored turned 90 degrees clockwise in the spritesheet. Never written, since the packer doesn't rotate frames
+    pub rotated: bool
 }
 
 impl Ord for SubTexture {
@@ -234,12 +299,26 @@ impl SubTexture
 {
     pub fn new(name: String, x: u32, y: u32, width: u32, height: u32, frame_x: Option<i32>, frame_y: Option<i32>, frame_width: Option<u32>, frame_height: Option<u32>, flip_x: Option<bool>, flip_y: Option<bool>) -> Self
     {
-        Self { name, x, y, width, height, frame_x, frame_y, frame_width, frame_height, flip_x, flip_y }
+        Self { name, x, y, width, height, frame_x, frame_y, frame_width, frame_height, flip_x, flip_y, rotated: false }
+    }
+
+    /// Cuts the subtexture's image out of its spritesheet, turning it back upright if it was stored rotated
+    pub fn upright_image(&self, spritesheet: &DynamicImage) -> DynamicImage
+    {
+        let img = spritesheet.crop_imm(self.x, self.y, self.width, self.height);
+        if self.rotated { img.rotate270() } else { img }
+    }
+
+    /// The frame rect of the upright image as `(frame_x, frame_y, frame_width, frame_height)`. Untrimmed subtextures fill their whole frame
+    pub fn frame_rect(&self) -> (i32, i32, u32, u32)
+    {
+        let (width, height) = if self.rotated { (self.height, self.width) } else { (self.width, self.height) };
+        (self.frame_x.unwrap_or(0), self.frame_y.unwrap_or(0), self.frame_width.unwrap_or(width), self.frame_height.unwrap_or(height))
     }
 }
 
 impl Default for SubTexture {
     fn default() -> Self {
-        Self { name: "".to_string(), x: 0, y: 0, width: 0, height: 0, frame_x: None, frame_y: None, frame_width: None, frame_height: None, flip_x: None, flip_y: None }
+        Self { name: "".to_string(), x: 0, y: 0, width: 0, height: 0, frame_x: None, frame_y: None, frame_width: None, frame_height: None, flip_x: None, flip_y: None, rotated: false }
     }
 }
\ No newline at end of file
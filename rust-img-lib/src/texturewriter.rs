@@ -0,0 +1,241 @@
+//! Writes spritesheets as block compressed DDS or KTX2 textures, for engines that upload them to the GPU as-is
+
+use image::RgbaImage;
+use wasm_bindgen::prelude::*;
+
+use crate::algorithms::blockcompression::{self, BlockCompression};
+
+/// The file formats GPU textures can be written in
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureContainer
+{
+    Dds,
+    Ktx2
+}
+
+/// Settings for writing a spritesheet as a GPU texture
+#[derive(Clone, Copy, Debug)]
+pub struct GpuTextureOptions
+{
+    pub container: TextureContainer,
+    pub compression: BlockCompression,
+    /// Also write every mip level down to 1x1
+    pub mipmaps: bool
+}
+
+impl GpuTextureOptions
+{
+    pub fn extension(&self) -> &'static str
+    {
+        match self.container {
+            TextureContainer::Dds => ".dds",
+            TextureContainer::Ktx2 => ".ktx2"
+        }
+    }
+}
+
+/// Encodes the image as a GPU texture. The image is padded with transparent pixels up to a multiple of 4 on both sides,
+/// since block compressed textures are made of 4x4 blocks
+pub fn encode_gpu_texture(img: &RgbaImage, options: &GpuTextureOptions, premultiplied: bool) -> Vec<u8>
+{
+    let (width, height) = (img.width().div_ceil(4).max(1) * 4, img.height().div_ceil(4).max(1) * 4);
+    let mut base = RgbaImage::new(width, height);
+    image::imageops::replace(&mut base, img, 0, 0);
+
+    let mut levels = vec![base];
+    if options.mipmaps
+    {
+        let mip_count = 32 - width.max(height).leading_zeros();
+        for _ in 1..mip_count
+        {
+            let next = downsample(levels.last().expect("There is always a base level"), premultiplied);
+            levels.push(next);
+        }
+    }
+
+    let compressed: Vec<Vec<u8>> = levels.iter().map(|level| blockcompression::compress_image(level, options.compression)).collect();
+    match options.container {
+        TextureContainer::Dds => write_dds(width, height, &compressed, options.compression, premultiplied),
+        TextureContainer::Ktx2 => write_ktx2(width, height, &compressed, options.compression, premultiplied)
+    }
+}
+
+/// Halves the image (rounding down, to at least 1 pixel) by averaging 2x2 areas, weighting the color channels by alpha
+fn downsample(img: &RgbaImage, premultiplied: bool) -> RgbaImage
+{
+    let (width, height) = ((img.width() / 2).max(1), (img.height() / 2).max(1));
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)]
+        {
+            let px = img.get_pixel((x * 2 + dx).min(img.width() - 1), (y * 2 + dy).min(img.height() - 1)).0;
+            let weight = if premultiplied { 255 } else { px[3] as u32 };
+            for (s, &c) in sum.iter_mut().zip(&px[..3])
+            {
+                *s += c as u32 * weight;
+            }
+            sum[3] += px[3] as u32;
+        }
+
+        let total_weight = if premultiplied { 255 * 4 } else { sum[3] };
+        if total_weight == 0
+        {
+            return image::Rgba([0, 0, 0, 0]);
+        }
+        image::Rgba([
+            ((sum[0] + total_weight / 2) / total_weight) as u8,
+            ((sum[1] + total_weight / 2) / total_weight) as u8,
+            ((sum[2] + total_weight / 2) / total_weight) as u8,
+            ((sum[3] + 2) / 4) as u8
+        ])
+    })
+}
+
+fn push_u32s(out: &mut Vec<u8>, values: &[u32])
+{
+    for value in values
+    {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+
+/// The "DDS " magic and the main header. `pixel_format` is the DDS_PIXELFORMAT struct without its size
+fn dds_header(width: u32, height: u32, flags: u32, pitch_or_linear_size: u32, mip_count: u32, caps: u32, pixel_format: [u32; 7]) -> Vec<u8>
+{
+    let mut out = b"DDS ".to_vec();
+    push_u32s(&mut out, &[124, DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | flags, height, width, pitch_or_linear_size, 0, mip_count]);
+    push_u32s(&mut out, &[0; 11]);
+    push_u32s(&mut out, &[32]);
+    push_u32s(&mut out, &pixel_format);
+    push_u32s(&mut out, &[DDSCAPS_TEXTURE | caps, 0, 0, 0, 0]);
+    out
+}
+
+fn write_dds(width: u32, height: u32, levels: &[Vec<u8>], compression: BlockCompression, premultiplied: bool) -> Vec<u8>
+{
+    const DDPF_FOURCC: u32 = 0x4;
+    const DDSCAPS_COMPLEX: u32 = 0x8;
+    const DDSCAPS_MIPMAP: u32 = 0x400000;
+    const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+    const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+    const DDS_ALPHA_MODE_STRAIGHT: u32 = 1;
+    const DDS_ALPHA_MODE_PREMULTIPLIED: u32 = 2;
+
+    let (mut flags, mut caps) = (DDSD_LINEARSIZE, 0);
+    if levels.len() > 1
+    {
+        flags |= DDSD_MIPMAPCOUNT;
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+    // BC7 has no FourCC of its own, so it needs the extended DX10 header
+    let four_cc = match compression {
+        BlockCompression::Bc1 => b"DXT1",
+        BlockCompression::Bc3 => b"DXT5",
+        BlockCompression::Bc7 => b"DX10"
+    };
+
+    let mut out = dds_header(width, height, flags, levels[0].len() as u32, levels.len() as u32, caps, [DDPF_FOURCC, u32::from_le_bytes(*four_cc), 0, 0, 0, 0, 0]);
+    if compression == BlockCompression::Bc7
+    {
+        let alpha_mode = if premultiplied { DDS_ALPHA_MODE_PREMULTIPLIED } else { DDS_ALPHA_MODE_STRAIGHT };
+        push_u32s(&mut out, &[DXGI_FORMAT_BC7_UNORM, D3D10_RESOURCE_DIMENSION_TEXTURE2D, 0, 1, alpha_mode]);
+    }
+
+    for level in levels
+    {
+        out.extend_from_slice(level);
+    }
+    out
+}
+
+/// Writes uncompressed 16-bit pixels as a DDS, described by their channel bit masks (red, green, blue, alpha)
+pub fn write_16bit_dds(width: u32, height: u32, pixels: &[u8], masks: [u32; 4]) -> Vec<u8>
+{
+    const DDPF_ALPHAPIXELS: u32 = 0x1;
+    const DDPF_RGB: u32 = 0x40;
+
+    let format_flags = if masks[3] != 0 { DDPF_RGB | DDPF_ALPHAPIXELS } else { DDPF_RGB };
+    let mut out = dds_header(width, height, DDSD_PITCH, width * 2, 1, 0, [format_flags, 0, 16, masks[0], masks[1], masks[2], masks[3]]);
+    out.extend_from_slice(pixels);
+    out
+}
+
+fn write_ktx2(width: u32, height: u32, levels: &[Vec<u8>], compression: BlockCompression, premultiplied: bool) -> Vec<u8>
+{
+    const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+    const HEADER_SIZE: usize = 80;
+    const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+    // (vkFormat, KHR_DF_MODEL_*, samples as (channel id, bit offset, bit length))
+    let (vk_format, color_model, samples): (u32, u8, &[(u8, u16, u8)]) = match compression {
+        // VK_FORMAT_BC1_RGBA_UNORM_BLOCK, KHR_DF_MODEL_BC1A, KHR_DF_CHANNEL_BC1A_ALPHAPRESENT
+        BlockCompression::Bc1 => (133, 128, &[(1, 0, 64)]),
+        // VK_FORMAT_BC3_UNORM_BLOCK, KHR_DF_MODEL_BC3, KHR_DF_CHANNEL_BC3_ALPHA then KHR_DF_CHANNEL_BC3_COLOR
+        BlockCompression::Bc3 => (137, 130, &[(15, 0, 64), (0, 64, 64)]),
+        // VK_FORMAT_BC7_UNORM_BLOCK, KHR_DF_MODEL_BC7, KHR_DF_CHANNEL_BC7_COLOR
+        BlockCompression::Bc7 => (145, 133, &[(0, 0, 128)])
+    };
+
+    // data format descriptor, with a single basic descriptor block
+    let block_size = 24 + 16 * samples.len();
+    let mut dfd = Vec::new();
+    push_u32s(&mut dfd, &[4 + block_size as u32, 0]);
+    dfd.extend_from_slice(&2u16.to_le_bytes());
+    dfd.extend_from_slice(&(block_size as u16).to_le_bytes());
+    // color model, BT.709 primaries, linear transfer, alpha flags
+    dfd.extend_from_slice(&[color_model, 1, 1, premultiplied as u8]);
+    // 4x4x1x1 texel blocks (stored minus one)
+    dfd.extend_from_slice(&[3, 3, 0, 0]);
+    dfd.extend_from_slice(&[compression.block_size() as u8, 0, 0, 0, 0, 0, 0, 0]);
+    for &(channel, bit_offset, bit_length) in samples
+    {
+        dfd.extend_from_slice(&bit_offset.to_le_bytes());
+        dfd.extend_from_slice(&[bit_length - 1, channel, 0, 0, 0, 0]);
+        push_u32s(&mut dfd, &[0, u32::MAX]);
+    }
+
+    let dfd_offset = HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE * levels.len();
+    // mip levels are stored smallest first, each aligned to the block size
+    let mut level_offsets = vec![0; levels.len()];
+    let mut offset = dfd_offset + dfd.len();
+    for (level, level_offset) in levels.iter().zip(level_offsets.iter_mut()).rev()
+    {
+        offset = offset.next_multiple_of(compression.block_size());
+        *level_offset = offset;
+        offset += level.len();
+    }
+
+    let mut out = IDENTIFIER.to_vec();
+    // vkFormat, typeSize, width, height, depth, layers, faces, levels, supercompression
+    push_u32s(&mut out, &[vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0]);
+    // dfd offset and length, key/value data offset and length
+    push_u32s(&mut out, &[dfd_offset as u32, dfd.len() as u32, 0, 0]);
+    // supercompression global data offset and length
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+    for (level, &level_offset) in levels.iter().zip(&level_offsets)
+    {
+        for value in [level_offset as u64, level.len() as u64, level.len() as u64]
+        {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&dfd);
+
+    for (level, &level_offset) in levels.iter().zip(&level_offsets).rev()
+    {
+        out.resize(level_offset, 0);
+        out.extend_from_slice(level);
+    }
+    out
+}
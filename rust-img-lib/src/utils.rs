@@ -2,7 +2,9 @@ use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::{Hash, Hasher}}
 
 use image::{ImageEncoder, GenericImageView, imageops};
 
-use crate::algorithms::spritesheetpackers::growingpacker::TransformInfo;
+use crate::quantize::{self, Quantization, QuantizationReport};
+use crate::pngwriter::{self, PngCompression, PngFilter, PngImageData};
+use crate::algorithms::{pixelscalers, spritesheetpackers::growingpacker::{TransformInfo, ResampleFilter}};
 
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -15,14 +17,157 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
-pub fn encode_image_as_png(img: &image::DynamicImage) -> Vec<u8>
+/// Options that control how images are written out as PNGs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PngOptions
+{
+    /// Multiply the color channels by alpha before writing, for engines that expect premultiplied textures
+    pub premultiply_alpha: bool,
+    /// Write an indexed (palette) PNG instead of a truecolor one
+    pub quantization: Option<Quantization>,
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    /// Try out lossless color type reductions and every filter, and keep whichever PNG comes out smallest
+    pub optimize: bool
+}
+
+pub fn encode_image_as_png(img: &image::DynamicImage, options: &PngOptions) -> Vec<u8>
+{
+    encode_image_as_png_with_report(img, options).0
+}
+
+/// Same as `encode_image_as_png`, but also returns how much error was introduced if the image had to be quantized
+pub fn encode_image_as_png_with_report(img: &image::DynamicImage, options: &PngOptions) -> (Vec<u8>, Option<QuantizationReport>)
+{
+    if options.premultiply_alpha || options.quantization.is_some() || options.optimize
+    {
+        let mut rgba_img = img.to_rgba8();
+        if options.premultiply_alpha
+        {
+            premultiply_alpha(&mut rgba_img);
+        }
+
+        let (img_data, report) = match options.quantization {
+            Some(quantization) => {
+                let (quantized, report) = quantize::quantize(&rgba_img, quantization);
+                (PngImageData::indexed(&quantized), Some(report))
+            },
+            None => (PngImageData::rgba(&rgba_img), None)
+        };
+        let pngbytes = pngwriter::write_png(&img_data, options.compression, options.filter);
+        if options.optimize
+        {
+            return (pngwriter::optimize(&img_data, pngbytes), report);
+        }
+        return (pngbytes, report);
+    }
+    (encode_png_bytes(img.as_bytes(), img.width(), img.height(), img.color(), options), None)
+}
+
+fn encode_png_bytes(bytes: &[u8], width: u32, height: u32, color: image::ColorType, options: &PngOptions) -> Vec<u8>
 {
     let mut out_vec = Vec::new();
-    let png_encoder = image::codecs::png::PngEncoder::new(&mut out_vec);
-    png_encoder.write_image(img.as_bytes(), img.width(), img.height(), img.color()).expect("Error writing png to buffer!");
+    let png_encoder = image::codecs::png::PngEncoder::new_with_quality(&mut out_vec, options.compression.into(), options.filter.into());
+    png_encoder.write_image(bytes, width, height, color).expect("Error writing png to buffer!");
     out_vec
 }
 
+pub fn premultiply_alpha(img: &mut image::RgbaImage)
+{
+    for px in img.pixels_mut()
+    {
+        let alpha = px.0[3] as u32;
+        for channel in &mut px.0[..3]
+        {
+            // rounded (channel * alpha / 255)
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+pub fn resize_image(img: &image::DynamicImage, new_width: u32, new_height: u32, filter: imageops::FilterType) -> image::DynamicImage
+{
+    if filter == imageops::FilterType::Nearest
+    {
+        // nearest neighbour never mixes pixels, so there's nothing to premultiply
+        img.resize_exact(new_width, new_height, filter)
+    }
+    else
+    {
+        resize_premultiplied(img, new_width, new_height, filter)
+    }
+}
+
+/// Resizes the image with its color channels premultiplied by alpha, so that transparent pixels don't bleed dark fringes into the result
+fn resize_premultiplied(img: &image::DynamicImage, new_width: u32, new_height: u32, filter: imageops::FilterType) -> image::DynamicImage
+{
+    let mut float_img = img.to_rgba32f();
+    for px in float_img.pixels_mut()
+    {
+        let alpha = px.0[3];
+        for channel in &mut px.0[..3]
+        {
+            *channel *= alpha;
+        }
+    }
+
+    let mut resized = imageops::resize(&float_img, new_width, new_height, filter);
+    for px in resized.pixels_mut()
+    {
+        // filters with negative lobes can overshoot, so clamp before undoing the premultiplication
+        let alpha = px.0[3].clamp(0.0, 1.0);
+        for channel in &mut px.0[..3]
+        {
+            *channel = if alpha > 0.0 { (*channel / alpha).clamp(0.0, 1.0) } else { 0.0 };
+        }
+        px.0[3] = alpha;
+    }
+    image::DynamicImage::ImageRgba32F(resized).into_rgba8().into()
+}
+
+/// Decodes every frame of an animated GIF, APNG or WebP, each one composited onto the full canvas the way it would be shown
+/// (disposal and blending included), along with how many milliseconds it's shown for. Images that aren't animated come back as a single frame with no delay.
+/// 
+/// GIF frames with delays of 10ms or less are given 100ms, which is what browsers play them at
+pub fn decode_animation_frames(img_data: &[u8]) -> Result<Vec<(image::RgbaImage, f64)>, image::ImageError>
+{
+    use image::{AnimationDecoder, codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder}};
+
+    let still_image = || -> Result<Vec<(image::RgbaImage, f64)>, image::ImageError> {
+        Ok(vec![(image::load_from_memory(img_data)?.to_rgba8(), 0.0)])
+    };
+    let cursor = std::io::Cursor::new(img_data);
+    let (frames, is_gif) = match image::guess_format(img_data)? {
+        image::ImageFormat::Gif => (GifDecoder::new(cursor)?.into_frames(), true),
+        image::ImageFormat::Png => {
+            let decoder = PngDecoder::new(cursor)?;
+            if !decoder.is_apng()
+            {
+                return still_image();
+            }
+            (decoder.apng().into_frames(), false)
+        },
+        image::ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(cursor)?;
+            if !decoder.has_animation()
+            {
+                return still_image();
+            }
+            (decoder.into_frames(), false)
+        },
+        _ => return still_image()
+    };
+    frames
+        .map(|frame| {
+            let frame = frame?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay_ms = numer as f64 / denom.max(1) as f64;
+            let delay_ms = if is_gif && delay_ms <= 10.0 { 100.0 } else { delay_ms };
+            Ok((frame.into_buffer(), delay_ms))
+        })
+        .collect()
+}
+
 pub fn get_hash_from_image_bytes(img_bytes: &[u8]) -> u64
 {
     let mut hasher = DefaultHasher::new();
@@ -71,17 +216,23 @@ pub fn get_bounding_box(img: &image::DynamicImage, alpha_threshold: Option<u8>)
     Some((bb_left, bb_top as u32, bb_right, bb_bottom))
 }
 
-pub fn transform_image(img: image::DynamicImage, img_transform: TransformInfo) -> image::DynamicImage
+pub fn transform_image(img: image::DynamicImage, img_transform: TransformInfo) -> Result<image::DynamicImage, TransformError>
 {
-    // first we scale then we flip
-    let mut new_img = img;
+    // first we recolor (so exact colors haven't been blended yet), then we scale, then we flip, then we rotate, and finally apply effects
+    let mut new_img = apply_color_ops(img, &img_transform.color_ops);
     if img_transform.new_width != new_img.width() || img_transform.new_height != new_img.height()
     {
-        new_img = new_img.resize_exact(
-            img_transform.new_width, 
-            img_transform.new_height, 
-            imageops::FilterType::Nearest
-        );
+        if img_transform.filter == ResampleFilter::IntegerNearest
+            && !(is_integer_upscale(new_img.width(), img_transform.new_width) && is_integer_upscale(new_img.height(), img_transform.new_height))
+        {
+            return Err(TransformError::NonIntegerScale);
+        }
+        let is_upscale = img_transform.new_width >= new_img.width() && img_transform.new_height >= new_img.height();
+        new_img = match img_transform.filter.pixel_scaler() {
+            Some(scaler) if is_upscale => pixelscalers::upscale(&new_img, scaler, img_transform.new_width, img_transform.new_height)
+                .ok_or(TransformError::UnsupportedPixelScale)?,
+            _ => resize_image(&new_img, img_transform.new_width, img_transform.new_height, img_transform.filter.into())
+        };
     }
 
     if img_transform.flip_x
@@ -93,7 +244,428 @@ pub fn transform_image(img: image::DynamicImage, img_transform: TransformInfo) -
         new_img = new_img.flipv();
     }
 
-    return new_img;
+    if img_transform.rotation != 0.0
+    {
+        new_img = rotate_image(&new_img, img_transform.rotation, img_transform.filter.into());
+    }
+
+    for effect in &img_transform.effects
+    {
+        new_img = apply_effect(new_img, effect);
+    }
+
+    Ok(new_img)
+}
+
+#[inline]
+fn is_integer_upscale(old_size: u32, new_size: u32) -> bool
+{
+    old_size != 0 && new_size >= old_size && new_size.is_multiple_of(old_size)
+}
+
+/// A color operation that can be applied to frames before they are cached
+#[derive(Clone, Debug)]
+pub enum ColorOp
+{
+    /// Replaces exact RGB colors with new ones. The alpha of each pixel is kept as is
+    PaletteMap(HashMap<[u8; 3], [u8; 3]>),
+    /// Rotates the hue by `hue` degrees, and adds `saturation` and `value` (both between -1 and 1)
+    HsvShift { hue: f32, saturation: f32, value: f32 },
+    /// Multiplies every pixel by the color
+    Tint([u8; 3])
+}
+
+/// How a solid background is turned into transparency when an image is imported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundRemoval
+{
+    /// Every pixel whose RGB is within `tolerance` of `color` on each channel
+    ColorKey { color: [u8; 3], tolerance: u8 },
+    /// Every pixel reachable from a corner of the image through pixels within `tolerance` of the background color, which is the color most corners share.
+    /// Unlike a color key, this leaves the background color alone where it's used inside the sprite
+    FloodFill { tolerance: u8 }
+}
+
+/// Makes the background of the image transparent. Removed pixels become `[0, 0, 0, 0]`, so they hash and trim like any other transparent pixel.
+/// The image is returned as it was (bit depth included) if nothing was removed
+pub fn remove_background(img: image::DynamicImage, removal: BackgroundRemoval) -> image::DynamicImage
+{
+    let mut rgba_img = img.to_rgba8();
+    let removed = match removal {
+        BackgroundRemoval::ColorKey { color, tolerance } => rgba_img.pixels().map(|px| channels_close(&px.0[..3], &color, tolerance)).collect(),
+        BackgroundRemoval::FloodFill { tolerance } => flood_fill_background(&rgba_img, tolerance)
+    };
+
+    let mut changed = false;
+    for (px, removed) in rgba_img.pixels_mut().zip(removed)
+    {
+        if removed && px.0 != [0, 0, 0, 0]
+        {
+            px.0 = [0, 0, 0, 0];
+            changed = true;
+        }
+    }
+    if changed { image::DynamicImage::ImageRgba8(rgba_img) } else { img }
+}
+
+#[inline]
+fn channels_close(a: &[u8], b: &[u8], tolerance: u8) -> bool
+{
+    a.iter().zip(b).all(|(x, y)| x.abs_diff(*y) <= tolerance)
+}
+
+/// Marks the background pixels for `BackgroundRemoval::FloodFill`. The background color is the one most of the corners share,
+/// and the fill only starts from corners of that color, so a sprite touching a corner isn't eaten. Transparent corners are already background
+fn flood_fill_background(img: &image::RgbaImage, tolerance: u8) -> Vec<bool>
+{
+    let (width, height) = img.dimensions();
+    let mut removed = vec![false; (width * height) as usize];
+    if width == 0 || height == 0
+    {
+        return removed;
+    }
+
+    let corners: Vec<(u32, u32)> = [(0, 0), (width - 1, 0), (0, height - 1), (width - 1, height - 1)]
+        .iter()
+        .copied()
+        .filter(|&(x, y)| img.get_pixel(x, y).0[3] != 0)
+        .collect();
+    // the first corner wins ties
+    let mut background = None;
+    let mut best_count = 0;
+    for &(x, y) in &corners
+    {
+        let color = img.get_pixel(x, y).0;
+        let count = corners.iter().filter(|&&(cx, cy)| channels_close(&img.get_pixel(cx, cy).0, &color, tolerance)).count();
+        if count > best_count
+        {
+            background = Some(color);
+            best_count = count;
+        }
+    }
+    let Some(background) = background else { return removed };
+
+    // only straight neighbours, so the fill can't leak through diagonal outlines
+    let mut stack = vec![];
+    for (x, y) in corners
+    {
+        if channels_close(&img.get_pixel(x, y).0, &background, tolerance) && !removed[(y * width + x) as usize]
+        {
+            removed[(y * width + x) as usize] = true;
+            stack.push((x, y));
+        }
+    }
+    while let Some((x, y)) = stack.pop()
+    {
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1)
+        ];
+        for (nx, ny) in neighbours
+        {
+            if nx >= width || ny >= height
+            {
+                continue;
+            }
+            let i = (ny * width + nx) as usize;
+            if !removed[i] && channels_close(&img.get_pixel(nx, ny).0, &background, tolerance)
+            {
+                removed[i] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    removed
+}
+
+/// Applies the color operations in order. The image is returned untouched if there are none
+pub fn apply_color_ops(img: image::DynamicImage, ops: &[ColorOp]) -> image::DynamicImage
+{
+    if ops.is_empty()
+    {
+        return img;
+    }
+
+    let mut rgba_img = img.into_rgba8();
+    for op in ops
+    {
+        for px in rgba_img.pixels_mut()
+        {
+            let [r, g, b, a] = px.0;
+            if a == 0
+            {
+                continue;
+            }
+            let [r, g, b] = match op {
+                ColorOp::PaletteMap(palette) => *palette.get(&[r, g, b]).unwrap_or(&[r, g, b]),
+                ColorOp::HsvShift { hue, saturation, value } => {
+                    let (h, s, v) = rgb_to_hsv([r, g, b]);
+                    hsv_to_rgb(
+                        (h + hue).rem_euclid(360.0),
+                        (s + saturation).clamp(0.0, 1.0),
+                        (v + value).clamp(0.0, 1.0)
+                    )
+                },
+                ColorOp::Tint(tint) => [
+                    ((r as u32 * tint[0] as u32 + 127) / 255) as u8,
+                    ((g as u32 * tint[1] as u32 + 127) / 255) as u8,
+                    ((b as u32 * tint[2] as u32 + 127) / 255) as u8
+                ]
+            };
+            px.0 = [r, g, b, a];
+        }
+    }
+    image::DynamicImage::ImageRgba8(rgba_img)
+}
+
+/// Converts to (hue in degrees, saturation, value)
+fn rgb_to_hsv(rgb: [u8; 3]) -> (f32, f32, f32)
+{
+    let [r, g, b] = rgb.map(|c| c as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue, saturation, max)
+}
+
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3]
+{
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = value - chroma;
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x)
+    };
+    [r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// An effect applied to a frame after it has been transformed. Effects can grow the frame
+#[derive(Clone, Debug)]
+pub enum ImageEffect
+{
+    /// A solid outline `thickness` pixels wide around everything that isn't fully transparent
+    Outline { thickness: u32, color: [u8; 4] },
+    /// A copy of the frame's shape, moved by the offset and blurred by `blur` (the gaussian's sigma), drawn behind the frame
+    DropShadow { offset_x: i32, offset_y: i32, blur: f32, color: [u8; 4] },
+    /// Fills the frame's shape with a flat color
+    Silhouette { color: [u8; 4] }
+}
+
+impl ImageEffect
+{
+    /// How much the effect grows the image by, as (left, top, right, bottom)
+    pub fn growth(&self) -> (u32, u32, u32, u32)
+    {
+        match *self {
+            ImageEffect::Outline { thickness, .. } => (thickness, thickness, thickness, thickness),
+            ImageEffect::DropShadow { offset_x, offset_y, blur, .. } => {
+                let blur_radius = (blur.max(0.0) * 3.0).ceil() as i32;
+                (
+                    (blur_radius - offset_x).max(0) as u32,
+                    (blur_radius - offset_y).max(0) as u32,
+                    (blur_radius + offset_x).max(0) as u32,
+                    (blur_radius + offset_y).max(0) as u32
+                )
+            },
+            ImageEffect::Silhouette { .. } => (0, 0, 0, 0)
+        }
+    }
+}
+
+/// Applies the effect, growing the image by `effect.growth()`
+pub fn apply_effect(img: image::DynamicImage, effect: &ImageEffect) -> image::DynamicImage
+{
+    let (left, top, right, bottom) = effect.growth();
+    let src = img.into_rgba8();
+    let (width, height) = (src.width() + left + right, src.height() + top + bottom);
+
+    let result = match *effect {
+        ImageEffect::Silhouette { color } => {
+            let mut silhouette = src;
+            for px in silhouette.pixels_mut()
+            {
+                let alpha = (px.0[3] as u32 * color[3] as u32 + 127) / 255;
+                px.0 = [color[0], color[1], color[2], alpha as u8];
+            }
+            silhouette
+        },
+        ImageEffect::Outline { thickness, color } => {
+            // offsets within a circle of radius `thickness`
+            let radius = thickness as i64;
+            let disk: Vec<(i64, i64)> = (-radius..=radius)
+                .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+                .filter(|(dx, dy)| dx * dx + dy * dy <= radius * radius)
+                .collect();
+
+            let mut outline = image::RgbaImage::new(width, height);
+            for (x, y, px) in outline.enumerate_pixels_mut()
+            {
+                let (src_x, src_y) = (x as i64 - left as i64, y as i64 - top as i64);
+                let coverage = disk
+                    .iter()
+                    .map(|(dx, dy)| get_pixel_or_transparent(&src, src_x + dx, src_y + dy).0[3])
+                    .max()
+                    .unwrap_or(0);
+                let alpha = (coverage as u32 * color[3] as u32 + 127) / 255;
+                *px = image::Rgba([color[0], color[1], color[2], alpha as u8]);
+            }
+            imageops::overlay(&mut outline, &src, left as i64, top as i64);
+            outline
+        },
+        ImageEffect::DropShadow { offset_x, offset_y, blur, color } => {
+            let mut shadow_mask = image::GrayImage::new(width, height);
+            for (x, y, px) in src.enumerate_pixels()
+            {
+                let shadow_x = (x + left) as i64 + offset_x as i64;
+                let shadow_y = (y + top) as i64 + offset_y as i64;
+                shadow_mask.put_pixel(shadow_x as u32, shadow_y as u32, image::Luma([px.0[3]]));
+            }
+            if blur > 0.0
+            {
+                shadow_mask = imageops::blur(&shadow_mask, blur);
+            }
+
+            let mut shadow = image::RgbaImage::from_fn(width, height, |x, y| {
+                let alpha = (shadow_mask.get_pixel(x, y).0[0] as u32 * color[3] as u32 + 127) / 255;
+                image::Rgba([color[0], color[1], color[2], alpha as u8])
+            });
+            imageops::overlay(&mut shadow, &src, left as i64, top as i64);
+            shadow
+        }
+    };
+    image::DynamicImage::ImageRgba8(result)
+}
+
+/// Returns the number of clockwise quarter turns if `degrees` is a multiple of 90
+pub fn as_quarter_turns(degrees: f32) -> Option<u32>
+{
+    let degrees = degrees.rem_euclid(360.0);
+    let quarter_turns = (degrees / 90.0).round();
+    if (degrees - quarter_turns * 90.0).abs() < 1e-4
+    {
+        Some(quarter_turns as u32 % 4)
+    }
+    else
+    {
+        None
+    }
+}
+
+/// The size of the canvas needed to hold a `width`x`height` image rotated by `degrees`
+pub fn rotated_dimensions(width: u32, height: u32, degrees: f32) -> (u32, u32)
+{
+    match as_quarter_turns(degrees) {
+        Some(0) | Some(2) => (width, height),
+        Some(_) => (height, width),
+        None => {
+            let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+            let (width, height) = (width as f64, height as f64);
+            (
+                (width * cos.abs() + height * sin.abs()).ceil() as u32,
+                (width * sin.abs() + height * cos.abs()).ceil() as u32
+            )
+        }
+    }
+}
+
+/// Rotates the image clockwise by `degrees` around its center. 
+/// 
+/// Multiples of 90 degrees are exact, any other angle expands the canvas to fit the whole rotated image
+pub fn rotate_image(img: &image::DynamicImage, degrees: f32, filter: imageops::FilterType) -> image::DynamicImage
+{
+    match as_quarter_turns(degrees) {
+        Some(0) => return img.clone(),
+        Some(1) => return img.rotate90(),
+        Some(2) => return img.rotate180(),
+        Some(3) => return img.rotate270(),
+        _ => ()
+    }
+
+    let (new_width, new_height) = rotated_dimensions(img.width(), img.height(), degrees);
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let src = img.to_rgba8();
+    let (src_width, src_height) = (src.width() as f64, src.height() as f64);
+    let rotated = image::RgbaImage::from_fn(new_width, new_height, |x, y| {
+        // undo the rotation to find where this pixel comes from in the source
+        let dx = x as f64 + 0.5 - new_width as f64 / 2.0;
+        let dy = y as f64 + 0.5 - new_height as f64 / 2.0;
+        let src_x = dx * cos + dy * sin + src_width / 2.0;
+        let src_y = -dx * sin + dy * cos + src_height / 2.0;
+        if filter == imageops::FilterType::Nearest
+        {
+            sample_nearest(&src, src_x, src_y)
+        }
+        else
+        {
+            sample_bilinear_premultiplied(&src, src_x, src_y)
+        }
+    });
+    image::DynamicImage::ImageRgba8(rotated)
+}
+
+fn get_pixel_or_transparent(img: &image::RgbaImage, x: i64, y: i64) -> image::Rgba<u8>
+{
+    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64
+    {
+        return image::Rgba([0, 0, 0, 0]);
+    }
+    *img.get_pixel(x as u32, y as u32)
+}
+
+fn sample_nearest(img: &image::RgbaImage, x: f64, y: f64) -> image::Rgba<u8>
+{
+    get_pixel_or_transparent(img, x.floor() as i64, y.floor() as i64)
+}
+
+fn sample_bilinear_premultiplied(img: &image::RgbaImage, x: f64, y: f64) -> image::Rgba<u8>
+{
+    // sample between pixel centers
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let mut premultiplied = [0.0f64; 4];
+    for (ox, oy, weight) in [(0, 0, (1.0 - tx) * (1.0 - ty)), (1, 0, tx * (1.0 - ty)), (0, 1, (1.0 - tx) * ty), (1, 1, tx * ty)]
+    {
+        let px = get_pixel_or_transparent(img, x0 + ox, y0 + oy).0;
+        let alpha = px[3] as f64 / 255.0;
+        for c in 0..3
+        {
+            premultiplied[c] += px[c] as f64 * alpha * weight;
+        }
+        premultiplied[3] += alpha * weight;
+    }
+
+    let alpha = premultiplied[3];
+    if alpha <= 0.0
+    {
+        return image::Rgba([0, 0, 0, 0]);
+    }
+    image::Rgba([
+        (premultiplied[0] / alpha).round().clamp(0.0, 255.0) as u8,
+        (premultiplied[1] / alpha).round().clamp(0.0, 255.0) as u8,
+        (premultiplied[2] / alpha).round().clamp(0.0, 255.0) as u8,
+        (alpha * 255.0).round().clamp(0.0, 255.0) as u8
+    ])
 }
 
 pub fn pad_image_uniform(img: image::DynamicImage, padding: u32) -> image::DynamicImage
@@ -103,6 +675,17 @@ pub fn pad_image_uniform(img: image::DynamicImage, padding: u32) -> image::Dynam
     padded_img
 }
 
+/// Makes a string safe to use as a file or folder name inside a zip, on any OS. Characters that aren't allowed are replaced with `_`
+pub fn sanitize_file_name(name: &str) -> String
+{
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    // windows doesn't allow names that end in dots or spaces
+    sanitized.trim_end_matches(['.', ' ']).to_string()
+}
+
 pub struct PrefixCounter
 {
     prefix_map: HashMap<String, u32>
@@ -138,4 +721,151 @@ impl std::fmt::Display for PackError {
         write!(f, "An error occurred while packing. Most likely due to invalid sorting")
     }
 }
-impl std::error::Error for PackError {}
\ No newline at end of file
+impl std::error::Error for PackError {}
+
+#[derive(Debug)]
+pub enum TransformError
+{
+    NonIntegerScale,
+    UnsupportedPixelScale
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransformError::NonIntegerScale => write!(f, "Integer scaling was requested but the new size is not a whole multiple of the original"),
+            TransformError::UnsupportedPixelScale => write!(f, "The pixel art upscaler can't reach the new size exactly. See the filter's docs for the factors it supports")
+        }
+    }
+}
+impl std::error::Error for TransformError {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn file_names_keep_allowed_characters()
+    {
+        assert_eq!(sanitize_file_name("idle"), "idle");
+        assert_eq!(sanitize_file_name("sing LEFT miss"), "sing LEFT miss");
+        assert_eq!(sanitize_file_name("Ñandú idle.v2"), "Ñandú idle.v2");
+    }
+
+    #[test]
+    fn file_names_replace_reserved_characters()
+    {
+        assert_eq!(sanitize_file_name("sing/LEFT"), "sing_LEFT");
+        assert_eq!(sanitize_file_name("..\\..\\evil"), ".._.._evil");
+        assert_eq!(sanitize_file_name("a:b*c?d\"e<f>g|h"), "a_b_c_d_e_f_g_h");
+        assert_eq!(sanitize_file_name("tab\there\nnewline"), "tab_here_newline");
+    }
+
+    #[test]
+    fn file_names_lose_trailing_dots_and_spaces()
+    {
+        assert_eq!(sanitize_file_name("idle. . "), "idle");
+        assert_eq!(sanitize_file_name(" idle"), " idle");
+        assert_eq!(sanitize_file_name(".."), "");
+        assert_eq!(sanitize_file_name(""), "");
+    }
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    /// Builds an image from rows of `.` (white), `#` (black), `r` (red) and ` ` (transparent)
+    fn from_art(rows: &[&str]) -> image::DynamicImage
+    {
+        let img = image::RgbaImage::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| image::Rgba(match rows[y as usize].as_bytes()[x as usize] {
+            b'#' => BLACK,
+            b'r' => RED,
+            b' ' => CLEAR,
+            _ => WHITE
+        }));
+        image::DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn color_key_removes_close_colors_everywhere()
+    {
+        let img = from_art(&["..#", "#.r"]);
+        let mut removed = remove_background(img, BackgroundRemoval::ColorKey { color: [250, 250, 250], tolerance: 5 }).to_rgba8();
+        assert_eq!(removed, from_art(&["  #", "# r"]).to_rgba8());
+
+        // just outside of the tolerance
+        removed = remove_background(from_art(&["..#"]), BackgroundRemoval::ColorKey { color: [250, 250, 250], tolerance: 4 }).to_rgba8();
+        assert_eq!(removed, from_art(&["..#"]).to_rgba8());
+    }
+
+    #[test]
+    fn flood_fill_removes_the_corner_color_around_the_sprite()
+    {
+        // the white inside the outline isn't reachable from the corners, so it stays
+        let img = from_art(&[
+            ".....",
+            ".###.",
+            ".#.#.",
+            ".###.",
+            "....."
+        ]);
+        let expected = from_art(&[
+            "     ",
+            " ### ",
+            " #.# ",
+            " ### ",
+            "     "
+        ]);
+        assert_eq!(remove_background(img, BackgroundRemoval::FloodFill { tolerance: 0 }).to_rgba8(), expected.to_rgba8());
+    }
+
+    #[test]
+    fn flood_fill_only_starts_from_background_corners()
+    {
+        // the sprite touches the bottom right corner, which doesn't make its color the background
+        let img = from_art(&[
+            "....",
+            "..rr",
+            ".rrr"
+        ]);
+        let expected = from_art(&[
+            "    ",
+            "  rr",
+            " rrr"
+        ]);
+        assert_eq!(remove_background(img, BackgroundRemoval::FloodFill { tolerance: 0 }).to_rgba8(), expected.to_rgba8());
+
+        // transparent corners don't count towards the background color, and the first corner wins a tie
+        let img = from_art(&[
+            " ...",
+            "r...",
+            "rr  "
+        ]);
+        let expected = from_art(&[
+            "    ",
+            "r   ",
+            "rr  "
+        ]);
+        assert_eq!(remove_background(img, BackgroundRemoval::FloodFill { tolerance: 0 }).to_rgba8(), expected.to_rgba8());
+    }
+
+    #[test]
+    fn images_without_a_background_are_left_alone()
+    {
+        // nothing matches, so the 16-bit image isn't converted
+        let img = image::DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(3, 3, image::Rgb([1000u16, 2000, 3000])));
+        let kept = remove_background(img.clone(), BackgroundRemoval::ColorKey { color: [255, 255, 255], tolerance: 0 });
+        assert_eq!(kept, img);
+
+        // the only matching pixels are already transparent
+        let img = image::DynamicImage::ImageLumaA8(image::ImageBuffer::from_fn(3, 3, |x, _| if x == 0 { image::LumaA([0, 0]) } else { image::LumaA([200, 255]) }));
+        let kept = remove_background(img.clone(), BackgroundRemoval::ColorKey { color: [0, 0, 0], tolerance: 0 });
+        assert_eq!(kept, img);
+
+        // every corner is transparent, so there's no background to fill from
+        let img = from_art(&[" . ", "...", " . "]);
+        assert_eq!(remove_background(img.clone(), BackgroundRemoval::FloodFill { tolerance: 0 }), img);
+    }
+}
@@ -0,0 +1,295 @@
+//! Reads a whole mod folder (zipped) of spritesheets, XMLs and loose frames, so JS doesn't have to add every frame itself
+
+use std::{cmp::Ordering, collections::HashMap, io::{Cursor, Read}};
+
+use wasm_bindgen::prelude::*;
+use zip::result::ZipError;
+
+use crate::textureatlas_format::TextureAtlas;
+
+/// Settings for `add_zip`. Every field can be changed from JS after creating it with `new`
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct ZipImportOptions
+{
+    /// Trims every frame to its bounding box, like `clip_to_bbox` on the other add functions
+    pub clip_to_bbox: bool,
+    /// Most digits removed from the end of a frame name to get its animation prefix. Every trailing digit is removed when unset
+    pub max_suffix_digits: Option<u32>
+}
+
+#[wasm_bindgen]
+impl ZipImportOptions
+{
+    /// Trims frames, and removes every trailing digit from frame names
+    pub fn new() -> Self
+    {
+        Self { clip_to_bbox: true, max_suffix_digits: None }
+    }
+}
+
+impl Default for ZipImportOptions
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// A spritesheet from the zip that had an XML with the same name next to it
+pub struct ZipAtlas
+{
+    /// Path of the spritesheet inside the zip
+    pub path: String,
+    pub image_data: Vec<u8>,
+    pub atlas: TextureAtlas
+}
+
+/// A PNG from the zip without an XML, added as a single frame
+pub struct ZipFrame
+{
+    /// Path of the frame inside the zip
+    pub path: String,
+    pub animation_prefix: String,
+    pub image_data: Vec<u8>
+}
+
+/// The spritesheets and loose frames of a zip, both in natural order of their paths
+pub struct ZipContents
+{
+    pub atlases: Vec<ZipAtlas>,
+    pub frames: Vec<ZipFrame>,
+    /// Files that couldn't be read and were left out, as `path: reason`
+    pub errors: Vec<String>
+}
+
+/// Pairs every `.png` in the zip with the `.xml` of the same path (if there is one). XMLs without a spritesheet and every other file are ignored.
+///
+/// Loose frames get their animation prefix from their file name with the numeric suffix removed (`idle0003.png` is part of `idle`).
+/// Frames named with only a number (`idle/0003.png`) use the folder they're in instead.
+///
+/// Files that can't be unzipped and XMLs that can't be read are left out (along with their spritesheet) and listed in `errors`.
+/// Only a zip that can't be opened at all is an error
+pub fn read_zip(zip_data: &[u8], options: &ZipImportOptions) -> Result<ZipContents, ZipError>
+{
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip_data))?;
+    let mut errors = vec![];
+    let mut pngs: Vec<(String, Vec<u8>)> = vec![];
+    // `None` for XMLs that couldn't be unzipped, so their spritesheet isn't mistaken for a loose frame
+    let mut xmls: HashMap<String, Option<(String, String)>> = HashMap::new();
+    for i in 0..archive.len()
+    {
+        let mut file = match archive.by_index(i) {
+            Ok(file) => file,
+            Err(err) => {
+                errors.push(format!("file #{}: {}", i, err));
+                continue;
+            }
+        };
+        let path = file.name().replace('\\', "/");
+        // macOS adds resource forks of every file to zips it makes
+        if file.is_dir() || path.starts_with("__MACOSX/")
+        {
+            continue;
+        }
+
+        let lower_path = path.to_lowercase();
+        if lower_path.ends_with(".png")
+        {
+            let mut data = vec![];
+            match file.read_to_end(&mut data) {
+                Ok(_) => pngs.push((path, data)),
+                Err(err) => errors.push(format!("{}: {}", path, err))
+            }
+        }
+        else if let Some(stem) = lower_path.strip_suffix(".xml")
+        {
+            let mut xml = String::new();
+            let xml = match file.read_to_string(&mut xml) {
+                Ok(_) => Some((path, xml)),
+                Err(err) => {
+                    errors.push(format!("{}: {}", path, err));
+                    None
+                }
+            };
+            xmls.insert(stem.to_string(), xml);
+        }
+    }
+    pngs.sort_by(|(a, _), (b, _)| natural_cmp(png_stem(a), png_stem(b)));
+
+    let mut contents = ZipContents { atlases: vec![], frames: vec![], errors };
+    for (path, image_data) in pngs
+    {
+        let stem = png_stem(&path);
+        match xmls.get(&stem.to_lowercase()) {
+            Some(Some((xml_path, xml))) => match TextureAtlas::from_xml_string(xml) {
+                Ok(atlas) => contents.atlases.push(ZipAtlas { path, image_data, atlas }),
+                Err(err) => contents.errors.push(format!("{}: {}", xml_path, err))
+            },
+            Some(None) => (),
+            None => {
+                let (folder, file_name) = stem.rsplit_once('/').unwrap_or(("", stem));
+                let mut animation_prefix = strip_numeric_suffix(file_name, options.max_suffix_digits);
+                if animation_prefix.is_empty()
+                {
+                    animation_prefix = folder.rsplit('/').next().unwrap_or("");
+                }
+                let animation_prefix = animation_prefix.to_string();
+                contents.frames.push(ZipFrame { path, animation_prefix, image_data });
+            }
+        }
+    }
+    Ok(contents)
+}
+
+/// The path of a PNG without its extension
+fn png_stem(path: &str) -> &str
+{
+    &path[..path.len() - ".png".len()]
+}
+
+/// Removes up to `max_digits` digits (or all of them) from the end of a frame name, leaving its animation prefix
+pub fn strip_numeric_suffix(name: &str, max_digits: Option<u32>) -> &str
+{
+    let max_digits = max_digits.map(|max| max as usize).unwrap_or(usize::MAX);
+    let digits = name.bytes().rev().take_while(|b| b.is_ascii_digit()).count().min(max_digits);
+    &name[..name.len() - digits]
+}
+
+/// Compares strings the way people count, so that `idle2` comes before `idle10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering
+{
+    let (mut a, mut b) = (a, b);
+    loop
+    {
+        let (a_digits, b_digits) = (leading_digits(a), leading_digits(b));
+        let both_numbers = a_digits > 0 && b_digits > 0;
+        // whole numbers are compared at once, anything else a character at a time
+        let (a_chunk, b_chunk) = if both_numbers {
+            (&a[..a_digits], &b[..b_digits])
+        } else {
+            (&a[..a.chars().next().map_or(0, char::len_utf8)], &b[..b.chars().next().map_or(0, char::len_utf8)])
+        };
+        if a_chunk.is_empty() || b_chunk.is_empty()
+        {
+            return a_chunk.len().cmp(&b_chunk.len());
+        }
+
+        let ordering = if both_numbers {
+            // longer numbers are bigger once leading zeros are gone, and equal numbers fall back to the zeros so `01` and `1` don't tie
+            let (a_number, b_number) = (a_chunk.trim_start_matches('0'), b_chunk.trim_start_matches('0'));
+            a_number.len().cmp(&b_number.len()).then_with(|| a_number.cmp(b_number)).then_with(|| a_chunk.len().cmp(&b_chunk.len()))
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if ordering != Ordering::Equal
+        {
+            return ordering;
+        }
+        a = &a[a_chunk.len()..];
+        b = &b[b_chunk.len()..];
+    }
+}
+
+fn leading_digits(s: &str) -> usize
+{
+    s.bytes().take_while(|b| b.is_ascii_digit()).count()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn numbers_sort_by_value()
+    {
+        let mut names = vec!["idle10", "idle2", "idle1", "idle02", "idle", "Idle3", "idle1a", "idle1b"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec!["Idle3", "idle", "idle1", "idle1a", "idle1b", "idle2", "idle02", "idle10"]);
+    }
+
+    #[test]
+    fn natural_order_compares_whole_numbers()
+    {
+        assert_eq!(natural_cmp("a99999999999999999999999", "a100000000000000000000000"), Ordering::Less);
+        assert_eq!(natural_cmp("sing2/0010", "sing10/0002"), Ordering::Less);
+        assert_eq!(natural_cmp("frame007", "frame007"), Ordering::Equal);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+        assert_eq!(natural_cmp("ä2", "ä10"), Ordering::Less);
+    }
+
+    #[test]
+    fn numeric_suffixes_are_stripped()
+    {
+        assert_eq!(strip_numeric_suffix("idle0003", None), "idle");
+        assert_eq!(strip_numeric_suffix("sing LEFT0012", None), "sing LEFT");
+        assert_eq!(strip_numeric_suffix("idle", None), "idle");
+        assert_eq!(strip_numeric_suffix("0003", None), "");
+        // animations named with a number keep it when only the frame digits are removed
+        assert_eq!(strip_numeric_suffix("phase20003", Some(4)), "phase2");
+        assert_eq!(strip_numeric_suffix("idle3", Some(4)), "idle");
+        assert_eq!(strip_numeric_suffix("idle0003", Some(0)), "idle0003");
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8>
+    {
+        let mut buffer = Cursor::new(vec![]);
+        {
+            let mut writer = zip::ZipWriter::new(&mut buffer);
+            for (path, data) in files
+            {
+                writer.start_file(*path, zip::write::FileOptions::default()).expect("Could not start a file");
+                writer.write_all(data).expect("Could not write a file");
+            }
+            writer.finish().expect("Could not finish the zip");
+        }
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn files_are_paired_and_sorted()
+    {
+        let xml = br#"<TextureAtlas imagePath="bf.png"><SubTexture name="idle0000" x="0" y="0" width="1" height="1"/></TextureAtlas>"#;
+        let contents = read_zip(&zip(&[
+            ("images/BF.xml", xml),
+            ("images/bf.png", b"sheet"),
+            ("loose/walk10.png", b"10"),
+            ("loose/walk2.png", b"2"),
+            ("jump/0001.png", b"1"),
+            ("__MACOSX/loose/._walk2.png", b"junk"),
+            ("readme.txt", b"hi")
+        ]), &ZipImportOptions::new()).expect("The zip should be readable");
+
+        assert_eq!(contents.atlases.len(), 1);
+        assert_eq!(contents.atlases[0].path, "images/bf.png");
+        assert_eq!(contents.atlases[0].atlas.subtextures.len(), 1);
+        let frames: Vec<(&str, &str)> = contents.frames.iter().map(|frame| (frame.path.as_str(), frame.animation_prefix.as_str())).collect();
+        assert_eq!(frames, vec![("jump/0001.png", "jump"), ("loose/walk2.png", "walk"), ("loose/walk10.png", "walk")]);
+        assert!(contents.errors.is_empty());
+    }
+
+    #[test]
+    fn unreadable_xmls_skip_their_spritesheet()
+    {
+        let contents = read_zip(&zip(&[
+            ("bad.xml", br#"<TextureAtlas><SubTexture name="a" x="left" y="0" width="1" height="1"/></TextureAtlas>"#),
+            ("bad.png", b"sheet"),
+            ("good.png", b"frame")
+        ]), &ZipImportOptions::new()).expect("The zip should be readable");
+
+        assert!(contents.atlases.is_empty());
+        assert_eq!(contents.frames.len(), 1);
+        assert_eq!(contents.frames[0].path, "good.png");
+        assert_eq!(contents.errors.len(), 1);
+        assert!(contents.errors[0].starts_with("bad.xml: "), "{}", contents.errors[0]);
+    }
+
+    #[test]
+    fn broken_zips_are_an_error()
+    {
+        assert!(read_zip(b"not a zip", &ZipImportOptions::new()).is_err());
+    }
+}